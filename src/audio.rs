@@ -1,32 +1,349 @@
 use audio::SoundSource;
-use ggez::{audio, Context};
+use ggez::event::{KeyCode, KeyMods};
+use ggez::{audio, filesystem, Context};
+use log::warn;
+use serde::{Deserialize, Serialize};
 use specs::{World, WorldExt};
 use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
+const CONFIG_PATH: &str = "/sounds/sounds.toml";
+const SETTINGS_PATH: &str = "/audio.toml";
+// shortest time between two plays of the same sound, so held keys stay bearable
+const MIN_INTERVAL: Duration = Duration::from_millis(90);
+
+// Sound file played for each event name, relative to /sounds
+#[derive(Default, Deserialize)]
+pub struct SoundConfig {
+    pub events: HashMap<String, String>,
+}
+
+// Volumes go from 0 to 1, the sfx and music ones being scaled by the master one
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub master_volume: f32,
+    pub sfx_volume: f32,
+    pub music_volume: f32,
+    pub muted: bool,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings {
+            master_volume: 1.0,
+            sfx_volume: 1.0,
+            music_volume: 0.6,
+            muted: false,
+        }
+    }
+}
+
+impl AudioSettings {
+    pub fn sfx_gain(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.master_volume * self.sfx_volume
+        }
+    }
+
+    pub fn music_gain(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.master_volume * self.music_volume
+        }
+    }
+}
+
+// Where sounds end up, so the game also runs without an audio device
+pub trait AudioBackend: Send + Sync {
+    // Prepare a sound file, failing if it cannot be played
+    fn load(&mut self, context: &mut Context, path: &str) -> Result<(), String>;
+    fn play(&mut self, path: &str, volume: f32);
+    // Called once a frame, for backends needing the context to output sound
+    fn update(&mut self, _context: &mut Context) {}
+    // Whether sounds reach an actual audio device
+    fn has_device(&self) -> bool {
+        false
+    }
+}
+
+// Plays sounds through the ggez audio device
 #[derive(Default)]
+pub struct GgezBackend {
+    sources: HashMap<String, audio::Source>,
+    pending: Vec<(String, f32)>,
+}
+
+impl AudioBackend for GgezBackend {
+    fn load(&mut self, context: &mut Context, path: &str) -> Result<(), String> {
+        let source = audio::Source::new(context, path).map_err(|e| e.to_string())?;
+        self.sources.insert(path.to_string(), source);
+        Ok(())
+    }
+
+    fn play(&mut self, path: &str, volume: f32) {
+        self.pending.push((path.to_string(), volume));
+    }
+
+    fn update(&mut self, context: &mut Context) {
+        for (path, volume) in self.pending.drain(..) {
+            if let Some(source) = self.sources.get_mut(&path) {
+                source.set_volume(volume);
+                let _ = source.play_detached(context);
+            }
+        }
+    }
+
+    fn has_device(&self) -> bool {
+        true
+    }
+}
+
+pub struct NullBackend;
+
+impl AudioBackend for NullBackend {
+    fn load(&mut self, _context: &mut Context, _path: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn play(&mut self, _path: &str, _volume: f32) {}
+}
+
+// Keeps the sounds played, cloned handles sharing them so tests can check them
+#[derive(Clone, Default)]
+pub struct RecordingBackend {
+    played: Arc<Mutex<Vec<(String, f32)>>>,
+}
+
+impl RecordingBackend {
+    pub fn played(&self) -> Vec<(String, f32)> {
+        self.played.lock().map(|p| p.clone()).unwrap_or_default()
+    }
+}
+
+impl AudioBackend for RecordingBackend {
+    fn load(&mut self, _context: &mut Context, _path: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn play(&mut self, path: &str, volume: f32) {
+        if let Ok(mut played) = self.played.lock() {
+            played.push((path.to_string(), volume));
+        }
+    }
+}
+
 pub struct AudioStore {
-    pub sounds: HashMap<String, audio::Source>,
+    pub backend: Box<dyn AudioBackend>,
+    // sound file of each event name
+    pub sounds: HashMap<String, String>,
+    pub settings: AudioSettings,
+    last_played: HashMap<String, Duration>,
+}
+
+impl Default for AudioStore {
+    fn default() -> Self {
+        AudioStore {
+            backend: Box::new(NullBackend),
+            sounds: HashMap::new(),
+            settings: AudioSettings::default(),
+            last_played: HashMap::new(),
+        }
+    }
 }
 
 impl AudioStore {
-    pub fn play_sound(&mut self, sound: &str, ctx: &mut Context) {
-        let _ = self
-            .sounds
-            .get_mut(sound)
-            .expect("expected sound")
-            .play_detached(ctx);
+    pub fn set_backend(&mut self, backend: impl AudioBackend + 'static) {
+        self.backend = Box::new(backend);
+    }
+
+    // Events without a sound are silently ignored
+    pub fn play_sound(&mut self, event: &str, now: Duration) {
+        let gain = self.settings.sfx_gain();
+        if gain <= 0.0 {
+            return;
+        }
+        let path = match self.sounds.get(event) {
+            Some(path) => path,
+            None => return,
+        };
+        if let Some(last) = self.last_played.get(event) {
+            if now.saturating_sub(*last) < MIN_INTERVAL {
+                return;
+            }
+        }
+        self.backend.play(path, gain);
+        self.last_played.insert(event.to_string(), now);
     }
 }
 
+fn read_file(context: &mut Context, path: &str) -> Option<String> {
+    let mut content = String::new();
+    filesystem::open(context, path)
+        .ok()
+        .and_then(|mut file| file.read_to_string(&mut content).ok())
+        .map(|_| content)
+}
+
+fn save_settings(context: &mut Context, settings: &AudioSettings) -> Result<(), String> {
+    let content = toml::to_string(settings).map_err(|e| e.to_string())?;
+    let mut file = filesystem::create(context, SETTINGS_PATH).map_err(|e| e.to_string())?;
+    file.write_all(content.as_bytes())
+        .map_err(|e| e.to_string())
+}
+
 pub fn initialize_sounds(world: &mut World, context: &mut Context) {
     let mut audio_store = world.write_resource::<AudioStore>();
-    let sounds = ["correct", "incorrect", "error"];
+    if let Some(content) = read_file(context, SETTINGS_PATH) {
+        match toml::from_str(&content) {
+            Ok(settings) => audio_store.settings = settings,
+            Err(e) => warn!("Ignoring audio settings: {}", e),
+        }
+    }
+
+    let config: SoundConfig = match read_file(context, CONFIG_PATH).map(|c| toml::from_str(&c)) {
+        Some(Ok(config)) => config,
+        Some(Err(e)) => {
+            warn!("Ignoring {}: {}", CONFIG_PATH, e);
+            return;
+        }
+        None => return,
+    };
+    for (event, file) in config.events {
+        let sound_path = format!("/sounds/{}", file);
+        match audio_store.backend.load(context, &sound_path) {
+            Ok(()) => {
+                audio_store.sounds.insert(event, sound_path);
+            }
+            Err(e) => warn!("Could not load {}: {}", sound_path, e),
+        }
+    }
+}
+
+pub fn update_audio(world: &mut World, context: &mut Context) {
+    world.write_resource::<AudioStore>().backend.update(context);
+}
+
+// F9 mutes, - and = change the master volume, the sfx one with shift and the
+// music one with ctrl
+pub fn handle_key(
+    world: &mut World,
+    context: &mut Context,
+    keycode: KeyCode,
+    keymod: KeyMods,
+) -> bool {
+    let mut audio_store = world.write_resource::<AudioStore>();
+    let settings = &mut audio_store.settings;
+    let step = match keycode {
+        KeyCode::F9 => {
+            settings.muted = !settings.muted;
+            0.0
+        }
+        KeyCode::Minus => -0.1,
+        KeyCode::Equals => 0.1,
+        _ => return false,
+    };
+    let volume = if keymod.contains(KeyMods::SHIFT) {
+        &mut settings.sfx_volume
+    } else if keymod.contains(KeyMods::CTRL) {
+        &mut settings.music_volume
+    } else {
+        &mut settings.master_volume
+    };
+    *volume = (*volume + step).clamp(0.0, 1.0);
+
+    if let Err(e) = save_settings(context, settings) {
+        warn!("Could not save audio settings: {}", e);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::RunNow;
+
+    use super::*;
+    use crate::events::{Event, EventBus};
+    use crate::systems::AudioSystem;
+
+    fn store() -> (AudioStore, RecordingBackend) {
+        let backend = RecordingBackend::default();
+        let mut audio_store = AudioStore::default();
+        audio_store.set_backend(backend.clone());
+        for event in ["box_pushed", "player_stepped"].iter() {
+            let path = format!("/sounds/{}.wav", event);
+            audio_store.sounds.insert(event.to_string(), path);
+        }
+        (audio_store, backend)
+    }
+
+    fn millis(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn muted_plays_nothing() {
+        let (mut audio_store, backend) = store();
+        audio_store.settings.muted = true;
+        audio_store.play_sound("box_pushed", millis(0));
+        assert!(backend.played().is_empty());
+
+        audio_store.settings.muted = false;
+        audio_store.play_sound("box_pushed", millis(0));
+        assert_eq!(backend.played().len(), 1);
+    }
+
+    #[test]
+    fn plays_with_sfx_gain() {
+        let (mut audio_store, backend) = store();
+        audio_store.settings.master_volume = 0.5;
+        audio_store.settings.sfx_volume = 0.4;
+        audio_store.play_sound("box_pushed", millis(0));
+        audio_store.play_sound("unknown", millis(0));
+
+        let played = backend.played();
+        assert_eq!(played.len(), 1);
+        assert_eq!(played[0].0, "/sounds/box_pushed.wav");
+        assert!((played[0].1 - 0.2).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn rate_limits_each_sound() {
+        let (mut audio_store, backend) = store();
+        audio_store.play_sound("box_pushed", millis(0));
+        audio_store.play_sound("box_pushed", millis(89));
+        audio_store.play_sound("player_stepped", millis(89));
+        assert_eq!(backend.played().len(), 2);
+
+        audio_store.play_sound("box_pushed", millis(90));
+        let played: Vec<String> = backend.played().into_iter().map(|(p, _)| p).collect();
+        assert_eq!(
+            played,
+            vec![
+                "/sounds/box_pushed.wav",
+                "/sounds/player_stepped.wav",
+                "/sounds/box_pushed.wav",
+            ]
+        );
+    }
 
-    for sound in sounds.iter() {
-        let sound_name = sound.to_string();
-        let sound_path = format!("/sounds/{}.wav", sound_name);
-        let sound_source = audio::Source::new(context, sound_path).expect("expected sound loaded");
+    #[test]
+    fn audio_system_plays_bus_events() {
+        let (audio_store, backend) = store();
+        let mut world = World::new();
+        world.insert(EventBus::default());
+        world.insert(audio_store);
+        let mut system = AudioSystem::default();
+        system.setup(&mut world);
 
-        audio_store.sounds.insert(sound_name, sound_source);
+        world.write_resource::<EventBus>().push(Event::BoxPushed);
+        system.run_now(&world);
+        let played: Vec<String> = backend.played().into_iter().map(|(p, _)| p).collect();
+        assert_eq!(played, vec!["/sounds/box_pushed.wav"]);
     }
 }
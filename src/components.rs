@@ -1,44 +1,44 @@
-use specs::{Component, NullStorage, VecStorage, World, WorldExt};
+use specs::{Component, FlaggedStorage, NullStorage, VecStorage, World, WorldExt};
 use std::fmt;
 use std::fmt::Display;
 
-#[derive(Debug, Component, Clone, Copy)]
-#[storage(VecStorage)]
+#[derive(Debug, Clone, Copy)]
 pub struct Position {
     pub x: u8,
     pub y: u8,
     pub z: u8,
 }
 
-pub enum RenderableKind {
-    Static,
-    Animated,
+// Flagged so the spatial index can follow what moves
+impl Component for Position {
+    type Storage = FlaggedStorage<Self, VecStorage<Self>>;
+}
+
+// Logical sprite of an entity, resolved to image files by the current theme
+#[derive(Clone)]
+pub enum Sprite {
+    Wall,
+    Floor,
+    Ice,
+    OneWay(Direction),
+    Teleporter,
+    Hole,
+    Plate,
+    Door { open: bool },
+    Player(u8),
+    Box(BoxColor),
+    BoxSpot(BoxColor),
 }
 
 #[derive(Component)]
 #[storage(VecStorage)]
 pub struct Renderable {
-    paths: Vec<String>,
+    pub sprite: Sprite,
 }
 
 impl Renderable {
-    pub fn new_static(path: String) -> Self {
-        Self { paths: vec![path] }
-    }
-
-    pub fn new_animated(paths: Vec<String>) -> Self {
-        Self { paths }
-    }
-    pub fn kind(&self) -> RenderableKind {
-        match self.paths.len() {
-            0 => panic!("invalid renderable"),
-            1 => RenderableKind::Static,
-            _ => RenderableKind::Animated,
-        }
-    }
-
-    pub fn path(&self, path_index: usize) -> String {
-        self.paths[path_index % self.paths.len()].clone()
+    pub fn new(sprite: Sprite) -> Self {
+        Self { sprite }
     }
 }
 
@@ -46,21 +46,31 @@ impl Renderable {
 #[storage(VecStorage)]
 pub struct Wall {}
 
+// Players are numbered from 0 in the reading order of the level
 #[derive(Component)]
 #[storage(VecStorage)]
-pub struct Player {}
+pub struct Player {
+    pub index: u8,
+}
 
-#[derive(PartialEq)]
+// Box and spot colors come from the level palette, neutral ones match anything
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum BoxColor {
-    Red,
-    Blue,
+    Neutral,
+    Colored(String),
+}
+
+impl BoxColor {
+    pub fn fits(&self, spot: &BoxColor) -> bool {
+        *self == BoxColor::Neutral || *spot == BoxColor::Neutral || self == spot
+    }
 }
 
 impl Display for BoxColor {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         fmt.write_str(match self {
-            BoxColor::Red => "red",
-            BoxColor::Blue => "blue",
+            BoxColor::Neutral => "neutral",
+            BoxColor::Colored(name) => name,
         })?;
         Ok(())
     }
@@ -78,7 +88,80 @@ pub struct BoxSpot {
     pub color: BoxColor,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    pub fn from_delta(dx: i32, dy: i32) -> Option<Direction> {
+        match (dx, dy) {
+            (0, -1) => Some(Direction::Up),
+            (0, 1) => Some(Direction::Down),
+            (-1, 0) => Some(Direction::Left),
+            (1, 0) => Some(Direction::Right),
+            _ => None,
+        }
+    }
+
+    pub fn delta(&self) -> (i32, i32) {
+        match self {
+            Direction::Up => (0, -1),
+            Direction::Down => (0, 1),
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+        }
+    }
+}
+
+// What an entity wants to do this frame, whoever asked for it: keyboard,
+// replay or script. The MovementSystem carries it out and clears it.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+#[storage(VecStorage)]
+pub enum MoveIntent {
+    Move(Direction),
+    Undo,
+}
+
+#[derive(Component)]
+#[storage(VecStorage)]
+pub struct OneWay {
+    pub direction: Direction,
+}
+
+#[derive(Component)]
+#[storage(VecStorage)]
+pub struct Teleporter {
+    pub pair: u8,
+}
+
+// Plates and doors sharing a link number go together
+#[derive(Component)]
+#[storage(VecStorage)]
+pub struct Plate {
+    pub link: u8,
+}
+
+// Closed doors are made Immovable
+#[derive(Component)]
+#[storage(VecStorage)]
+pub struct Door {
+    pub link: u8,
+    pub open: bool,
+}
+
 // Markers
+#[derive(Component, Default)]
+#[storage(NullStorage)]
+pub struct Ice;
+
+#[derive(Component, Default)]
+#[storage(NullStorage)]
+pub struct Hole;
+
 #[derive(Component, Default)]
 #[storage(NullStorage)]
 pub struct Movable;
@@ -97,4 +180,11 @@ pub fn register_components(world: &mut World) {
     world.register::<BoxSpot>();
     world.register::<Movable>();
     world.register::<Immovable>();
+    world.register::<MoveIntent>();
+    world.register::<Ice>();
+    world.register::<OneWay>();
+    world.register::<Teleporter>();
+    world.register::<Hole>();
+    world.register::<Plate>();
+    world.register::<Door>();
 }
@@ -1,4 +1,13 @@
-
 pub const TILE_WIDTH: f32 = 32.0;
-pub const MAP_WIDTH: u8 = 8;
-pub const MAP_HEIGHT: u8 = 9;
+// Largest level the window shows, left of the side panel
+pub const MAX_LEVEL_WIDTH: usize = 16;
+pub const MAX_LEVEL_HEIGHT: usize = 18;
+
+// Players sharing a level, each tinted with its color past the first one
+pub const MAX_PLAYERS: usize = 4;
+pub const PLAYER_COLORS: [(u8, u8, u8); MAX_PLAYERS] = [
+    (255, 255, 255),
+    (90, 140, 230),
+    (230, 120, 60),
+    (110, 190, 90),
+];
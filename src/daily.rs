@@ -0,0 +1,222 @@
+use ggez::{filesystem, Context};
+use log::{info, warn};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use specs::{World, WorldExt};
+use std::io::{Read, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::generator::{generate, GeneratorOptions, RandomLevels};
+use crate::map::{reset_world, spawn_level, CurrentLevel};
+use crate::resources::{Gameplay, GameplayState, Time};
+
+const RECORD_PATH: &str = "/daily.toml";
+// 2026-01-01, the day of the first daily puzzle
+const FIRST_DAY: u64 = 20454;
+
+// Streaks saved between sessions
+#[derive(Default, Deserialize, Serialize)]
+pub struct DailyRecord {
+    pub last_solved_day: Option<u64>,
+    pub streak: u32,
+    pub best_streak: u32,
+}
+
+impl DailyRecord {
+    pub fn record(&mut self, day: u64) {
+        if self.last_solved_day == Some(day) {
+            return;
+        }
+        self.streak = if self.last_solved_day == Some(day.saturating_sub(1)) {
+            self.streak + 1
+        } else {
+            1
+        };
+        self.best_streak = self.best_streak.max(self.streak);
+        self.last_solved_day = Some(day);
+    }
+
+    // A streak is only alive if yesterday's or today's puzzle was solved
+    pub fn current_streak(&self, day: u64) -> u32 {
+        match self.last_solved_day {
+            Some(last) if last + 1 >= day => self.streak,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Daily {
+    pub active: bool,
+    pub day: u64,
+    pub level: String,
+    pub started: Duration,
+    pub result: Option<String>,
+    pub record: DailyRecord,
+}
+
+pub fn today() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    now.as_secs() / 86_400
+}
+
+// Civil date of a day counted from the Unix epoch
+pub fn date(day: u64) -> String {
+    let z = day as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+pub fn puzzle_number(day: u64) -> u64 {
+    day.saturating_sub(FIRST_DAY) + 1
+}
+
+// Same puzzle for everyone on a given day: the generator only depends on the seed
+pub fn daily_options() -> GeneratorOptions {
+    GeneratorOptions {
+        width: 9,
+        height: 8,
+        boxes: 3,
+        colors: 2,
+        attempts: 20,
+    }
+}
+
+pub fn start_daily(world: &mut World) {
+    let day = today();
+    let mut rng = ChaCha8Rng::seed_from_u64(day);
+    let generated = match generate(&mut rng, &daily_options()) {
+        Some(generated) => generated,
+        None => return,
+    };
+
+    world.write_resource::<RandomLevels>().active = false;
+    reset_world(world);
+    // left unoriented, so that everyone shares the same puzzle and results
+    spawn_level(world, generated.level);
+    let level = world.read_resource::<CurrentLevel>().level.to_string();
+    let started = world.read_resource::<Time>().delta;
+    let mut daily = world.write_resource::<Daily>();
+    daily.active = true;
+    daily.day = day;
+    daily.level = level;
+    daily.started = started;
+    daily.result = None;
+}
+
+// Drops the daily title and result once another level is played
+pub fn leave_daily(world: &mut World) {
+    let mut daily = world.write_resource::<Daily>();
+    daily.active = false;
+    daily.result = None;
+}
+
+fn share_string(daily: &Daily, gameplay: &Gameplay, elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs();
+    format!(
+        "Sokoban Daily #{} ({})\nSolved in {}:{:02}\n{} moves, {} pushes\nStreak {}",
+        puzzle_number(daily.day),
+        date(daily.day),
+        seconds / 60,
+        seconds % 60,
+        gameplay.moves_count,
+        gameplay.pushes_count,
+        daily.record.current_streak(daily.day)
+    )
+}
+
+pub fn load_record(world: &mut World, context: &mut Context) {
+    let mut content = String::new();
+    let loaded = filesystem::open(context, RECORD_PATH)
+        .ok()
+        .and_then(|mut file| file.read_to_string(&mut content).ok());
+    if loaded.is_some() {
+        match toml::from_str(&content) {
+            Ok(record) => world.write_resource::<Daily>().record = record,
+            Err(e) => warn!("Ignoring daily record: {}", e),
+        }
+    }
+}
+
+fn save_record(context: &mut Context, record: &DailyRecord) -> Result<(), String> {
+    let content = toml::to_string(record).map_err(|e| e.to_string())?;
+    let mut file = filesystem::create(context, RECORD_PATH).map_err(|e| e.to_string())?;
+    file.write_all(content.as_bytes())
+        .map_err(|e| e.to_string())
+}
+
+// Record the daily puzzle once it is solved
+pub fn update_daily(world: &mut World, context: &mut Context) {
+    let gameplay = world.read_resource::<Gameplay>();
+    let mut daily = world.write_resource::<Daily>();
+    if !daily.active || daily.result.is_some() || gameplay.state != GameplayState::Won {
+        return;
+    }
+    // Only the untouched daily level counts, not an edited one
+    if world.read_resource::<CurrentLevel>().level.to_string() != daily.level {
+        daily.active = false;
+        return;
+    }
+
+    let elapsed = world.read_resource::<Time>().delta - daily.started;
+    let day = daily.day;
+    daily.record.record(day);
+    if let Err(e) = save_record(context, &daily.record) {
+        warn!("Could not save daily record: {}", e);
+    }
+    let result = share_string(&daily, &gameplay, elapsed);
+    info!("{}", result);
+    daily.result = Some(result);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_counts_streaks() {
+        let mut record = DailyRecord::default();
+        record.record(10);
+        record.record(11);
+        // solving the same day again changes nothing
+        record.record(11);
+        assert_eq!((record.streak, record.best_streak), (2, 2));
+
+        // day 12 was missed
+        record.record(13);
+        assert_eq!(record.last_solved_day, Some(13));
+        assert_eq!((record.streak, record.best_streak), (1, 2));
+    }
+
+    #[test]
+    fn current_streak_ends_after_a_missed_day() {
+        let mut record = DailyRecord::default();
+        assert_eq!(record.current_streak(10), 0);
+        record.record(10);
+        record.record(11);
+        assert_eq!(record.current_streak(11), 2);
+        assert_eq!(record.current_streak(12), 2);
+        assert_eq!(record.current_streak(13), 0);
+    }
+
+    #[test]
+    fn date_converts_days() {
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(FIRST_DAY - 1), "2025-12-31");
+        assert_eq!(date(FIRST_DAY), "2026-01-01");
+        assert_eq!(date(FIRST_DAY + 31), "2026-02-01");
+        assert_eq!(date(19_782), "2024-02-29");
+        assert_eq!(date(19_783), "2024-03-01");
+        assert_eq!(puzzle_number(FIRST_DAY), 1);
+    }
+}
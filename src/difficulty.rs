@@ -0,0 +1,222 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fmt::Display;
+
+use crate::components::BoxColor;
+use crate::map::{Level, Object, Tile};
+use crate::normalize::trim;
+use crate::solver::{solve, Board, Search, DIRECTIONS, MAX_NODES};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rating {
+    Easy,
+    Medium,
+    Hard,
+    Expert,
+    // not solved within the search limit
+    Unsolved,
+    // ice, one-way, teleporter, hole, plate or door tiles, which the solver
+    // doesn't know
+    Unsupported,
+}
+
+impl Display for Rating {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(match self {
+            Rating::Easy => "easy",
+            Rating::Medium => "medium",
+            Rating::Hard => "hard",
+            Rating::Expert => "expert",
+            Rating::Unsolved => "unsolved",
+            Rating::Unsupported => "unsupported tiles",
+        })
+    }
+}
+
+pub struct Difficulty {
+    pub nodes: usize,
+    pub pushes: Option<usize>,
+    pub boxes: usize,
+    pub colors: usize,
+    pub dead_squares: usize,
+    pub corridors: usize,
+    pub score: f32,
+    pub rating: Rating,
+}
+
+impl Display for Difficulty {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let pushes = self
+            .pushes
+            .map_or_else(|| "-".to_string(), |p| p.to_string());
+        write!(
+            fmt,
+            "{} (score {:.1}): {} pushes, {} nodes, {} boxes, {} colors, {} dead squares, {} corridors",
+            self.rating,
+            self.score,
+            pushes,
+            self.nodes,
+            self.boxes,
+            self.colors,
+            self.dead_squares,
+            self.corridors
+        )
+    }
+}
+
+// Floor cells squeezed between two walls on the same axis
+fn corridor_cells(board: &Board) -> Vec<bool> {
+    let wall = |cell: usize, direction| board.step(cell, direction).is_none_or(|c| board.walls[c]);
+    (0..board.walls.len())
+        .map(|cell| {
+            let [left, up, right, down] = DIRECTIONS;
+            !board.walls[cell]
+                && ((wall(cell, left) && wall(cell, right)) || (wall(cell, up) && wall(cell, down)))
+        })
+        .collect()
+}
+
+// Number of connected runs of corridor cells
+fn corridors(board: &Board) -> usize {
+    let corridor = corridor_cells(board);
+    let mut visited = vec![false; corridor.len()];
+    let mut count = 0;
+    for start in 0..corridor.len() {
+        if !corridor[start] || visited[start] {
+            continue;
+        }
+        count += 1;
+        visited[start] = true;
+        let mut stack = vec![start];
+        while let Some(cell) = stack.pop() {
+            for direction in DIRECTIONS.iter() {
+                if let Some(next) = board.step(cell, *direction) {
+                    if corridor[next] && !visited[next] {
+                        visited[next] = true;
+                        stack.push(next);
+                    }
+                }
+            }
+        }
+    }
+    count
+}
+
+// Only what the players can reach is rated, the rest of the level being trimmed
+pub fn estimate(level: &Level) -> Difficulty {
+    let level = &trim(level);
+    let unsupported = level.has_special_tiles();
+    let (board, _) = Board::new(level);
+    let search = if unsupported {
+        Search {
+            solution: None,
+            nodes: 0,
+        }
+    } else {
+        solve(level, MAX_NODES)
+    };
+    let live = board.live_cells();
+    let dead_squares = (0..board.walls.len())
+        .filter(|cell| !board.walls[*cell] && !live[*cell])
+        .count();
+
+    let mut boxes = 0;
+    let mut colors = HashSet::new();
+    for y in 0..level.height {
+        for x in 0..level.width {
+            let cell = level.cell(x, y);
+            if let (Tile::Floor, Some(Object::Box(color))) = (cell.tile, &cell.object) {
+                boxes += 1;
+                if *color != BoxColor::Neutral {
+                    colors.insert(color.clone());
+                }
+            }
+        }
+    }
+    let corridors = corridors(&board);
+
+    let pushes = search.solution.map(|solution| solution.pushes);
+    // Solution length weighs the most, search effort and board features refine it
+    let score = pushes.unwrap_or(0) as f32
+        + 3.0 * (search.nodes as f32 + 1.0).log2()
+        + 2.0 * boxes as f32
+        + 2.0 * colors.len() as f32
+        + corridors as f32
+        + 0.2 * dead_squares as f32;
+    let rating = match (pushes, score) {
+        _ if unsupported => Rating::Unsupported,
+        (None, _) => Rating::Unsolved,
+        (_, s) if s < 40.0 => Rating::Easy,
+        (_, s) if s < 70.0 => Rating::Medium,
+        (_, s) if s < 100.0 => Rating::Hard,
+        _ => Rating::Expert,
+    };
+
+    Difficulty {
+        nodes: search.nodes,
+        pushes,
+        boxes,
+        colors: colors.len(),
+        dead_squares,
+        corridors,
+        score,
+        rating,
+    }
+}
+
+// Print the difficulty of each level file, from the easiest to the hardest
+pub fn run_cli(paths: &[String]) -> Result<(), String> {
+    let mut results = Vec::new();
+    for path in paths {
+        let content = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let level = Level::parse(&content).map_err(|errors| {
+            let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            format!("{}: {}", path, errors.join(", "))
+        })?;
+        results.push((path, estimate(&level)));
+    }
+
+    results.sort_by(|a, b| {
+        (a.1.rating, a.1.score)
+            .partial_cmp(&(b.1.rating, b.1.score))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    for (path, difficulty) in results {
+        println!("{}: {}", path, difficulty);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate(map: &str) -> Difficulty {
+        estimate(&Level::parse(map).expect("expected a valid level"))
+    }
+
+    #[test]
+    fn rates_a_single_push() {
+        let difficulty = rate("W W W W W\nW P B S W\nW W W W W\n");
+        assert_eq!(difficulty.rating, Rating::Easy);
+        assert_eq!(difficulty.pushes, Some(1));
+        assert_eq!(difficulty.boxes, 1);
+    }
+
+    #[test]
+    fn special_tiles_are_unsupported() {
+        let difficulty = rate("W W W W W W\nW P B I S W\nW W W W W W\n");
+        assert_eq!(difficulty.rating, Rating::Unsupported);
+        assert_eq!(difficulty.pushes, None);
+        assert!(difficulty.to_string().starts_with("unsupported tiles"));
+    }
+
+    #[test]
+    fn unreachable_areas_are_ignored() {
+        let level = rate("W W W W W\nW P B S W\nW W W W W\n");
+        let with_pocket = rate("W W W W W W W W\nW P B S W . . W\nW W W W W W W W\n");
+        assert_eq!(with_pocket.dead_squares, level.dead_squares);
+        assert_eq!(with_pocket.corridors, level.corridors);
+        assert_eq!(with_pocket.rating, level.rating);
+    }
+}
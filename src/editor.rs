@@ -0,0 +1,303 @@
+use ggez::event::KeyCode;
+use ggez::{filesystem, Context};
+use specs::{World, WorldExt};
+use std::io::{Read, Write};
+
+use crate::components::{BoxColor, Sprite};
+use crate::constants::{MAX_LEVEL_HEIGHT, MAX_LEVEL_WIDTH, TILE_WIDTH};
+use crate::daily::leave_daily;
+use crate::map::{reset_world, spawn_level, Cell, CurrentLevel, Level, Object, Tile};
+use crate::resources::RuleMode;
+
+pub const PANEL_X: f32 = 760.0;
+pub const PANEL_Y: f32 = 20.0;
+pub const PANEL_STEP: f32 = 40.0;
+const LEVEL_PATH: &str = "/levels/custom.txt";
+
+#[derive(Clone, PartialEq)]
+pub enum Brush {
+    Wall,
+    Floor,
+    Erase,
+    Player,
+    Box(BoxColor),
+    Spot(BoxColor),
+}
+
+impl Brush {
+    pub fn sprite(&self) -> Option<Sprite> {
+        match self {
+            Brush::Wall => Some(Sprite::Wall),
+            Brush::Floor => Some(Sprite::Floor),
+            Brush::Erase => None,
+            Brush::Player => Some(Sprite::Player(0)),
+            Brush::Box(color) => Some(Sprite::Box(color.clone())),
+            Brush::Spot(color) => Some(Sprite::BoxSpot(color.clone())),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Brush::Wall => "wall".to_string(),
+            Brush::Floor => "floor".to_string(),
+            Brush::Erase => "erase".to_string(),
+            Brush::Player => "player".to_string(),
+            Brush::Box(color) => format!("{} box", color),
+            Brush::Spot(color) => format!("{} spot", color),
+        }
+    }
+}
+
+// Resource holding the level being edited, mirrored into the world after each change
+pub struct Editor {
+    pub active: bool,
+    pub playtesting: bool,
+    pub level: Level,
+    pub brush: Brush,
+    pub painting: bool,
+    pub messages: Vec<String>,
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Self {
+            active: false,
+            playtesting: false,
+            level: Level::default(),
+            brush: Brush::Wall,
+            painting: false,
+            messages: Vec::new(),
+        }
+    }
+}
+
+impl Editor {
+    pub fn is_editing(&self) -> bool {
+        self.active && !self.playtesting
+    }
+
+    pub fn brushes(&self) -> Vec<Brush> {
+        let mut brushes = vec![
+            Brush::Wall,
+            Brush::Floor,
+            Brush::Erase,
+            Brush::Player,
+            Brush::Box(BoxColor::Neutral),
+            Brush::Spot(BoxColor::Neutral),
+        ];
+        for color in self.level.palette.colors.iter() {
+            let color = BoxColor::Colored(color.name.clone());
+            brushes.push(Brush::Box(color.clone()));
+            brushes.push(Brush::Spot(color));
+        }
+        brushes
+    }
+
+    pub fn paint(&mut self, x: usize, y: usize) {
+        if x >= self.level.width || y >= self.level.height {
+            return;
+        }
+        let brush = self.brush.clone();
+        let cell = self.level.cell_mut(x, y);
+        let floor = |cell: &Cell| Cell {
+            tile: Tile::Floor,
+            ..cell.clone()
+        };
+
+        *cell = match &brush {
+            Brush::Wall => Cell::new(Tile::Wall),
+            Brush::Floor => Cell::new(Tile::Floor),
+            Brush::Erase => Cell::new(Tile::Nothing),
+            Brush::Player => Cell {
+                object: Some(Object::Player),
+                ..floor(cell)
+            },
+            Brush::Box(color) => Cell {
+                object: Some(Object::Box(color.clone())),
+                // A box only starts on a spot of its own color, as in the map format
+                spot: cell.spot.clone().filter(|spot| spot == color),
+                ..floor(cell)
+            },
+            Brush::Spot(color) => Cell {
+                spot: Some(color.clone()),
+                object: cell
+                    .object
+                    .clone()
+                    .filter(|object| !matches!(object, Object::Box(c) if c != color)),
+                ..floor(cell)
+            },
+        };
+    }
+}
+
+// Respawn the edited level into the world, so it is drawn and playable
+fn refresh(world: &mut World) {
+    let level = {
+        let mut editor = world.write_resource::<Editor>();
+        editor.messages = editor
+            .level
+            .validate()
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        if editor.messages.is_empty() {
+            editor.messages.push("level is valid".to_string());
+        }
+        editor.level.clone()
+    };
+    reset_world(world);
+    spawn_level(world, level);
+}
+
+// Keeps an invalid level from being played, telling what to fix instead
+fn unplayable(editor: &mut Editor) -> bool {
+    let errors = editor.level.validate();
+    if errors.is_empty() {
+        return false;
+    }
+    editor.messages = errors.iter().map(|e| e.to_string()).collect();
+    editor
+        .messages
+        .push("fix the level before playing it".to_string());
+    true
+}
+
+pub fn toggle_editor(world: &mut World) {
+    {
+        let level = world.read_resource::<CurrentLevel>().level.clone();
+        let mut editor = world.write_resource::<Editor>();
+        if editor.active && unplayable(&mut editor) {
+            return;
+        }
+        editor.active = !editor.active;
+        editor.playtesting = false;
+        editor.painting = false;
+        if editor.active {
+            editor.level = level;
+        }
+    }
+    leave_daily(world);
+    refresh(world);
+}
+
+fn save_level(context: &mut Context, level: &Level) -> Result<String, String> {
+    let _ = filesystem::create_dir(context, "/levels");
+    let mut file = filesystem::create(context, LEVEL_PATH).map_err(|e| e.to_string())?;
+    file.write_all(level.to_string().as_bytes())
+        .map_err(|e| e.to_string())?;
+    Ok(format!(
+        "saved to {}{}",
+        filesystem::user_config_dir(context).display(),
+        LEVEL_PATH
+    ))
+}
+
+fn open_level(context: &mut Context) -> Result<Level, String> {
+    let mut file = filesystem::open(context, LEVEL_PATH).map_err(|e| e.to_string())?;
+    let mut content = String::new();
+    file.read_to_string(&mut content)
+        .map_err(|e| e.to_string())?;
+    Level::parse(&content).map_err(|errors| {
+        errors
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    })
+}
+
+// Returns true when the key was consumed by the editor: F2 always, and every
+// key while editing, so that F5, F6 or Backspace can't replace the edited level
+pub fn handle_key(world: &mut World, context: &mut Context, keycode: KeyCode) -> bool {
+    if keycode == KeyCode::F2 {
+        toggle_editor(world);
+        return true;
+    }
+    if !world.read_resource::<Editor>().active {
+        return false;
+    }
+    if keycode == KeyCode::Tab {
+        let mut editor = world.write_resource::<Editor>();
+        if !editor.playtesting && unplayable(&mut editor) {
+            return true;
+        }
+        editor.playtesting = !editor.playtesting;
+    } else if world.read_resource::<Editor>().playtesting {
+        return false;
+    }
+
+    let mut editor = world.write_resource::<Editor>();
+    let (width, height) = (editor.level.width, editor.level.height);
+    match keycode {
+        KeyCode::Left => editor.level.resize(width.saturating_sub(1).max(1), height),
+        // only growing is capped, so that opening a larger level doesn't crop it
+        KeyCode::Right if width < MAX_LEVEL_WIDTH => editor.level.resize(width + 1, height),
+        KeyCode::Up => editor.level.resize(width, height.saturating_sub(1).max(1)),
+        KeyCode::Down if height < MAX_LEVEL_HEIGHT => editor.level.resize(width, height + 1),
+        KeyCode::C => {
+            editor.level.rule_mode = match editor.level.rule_mode {
+                RuleMode::Colored => RuleMode::Classic,
+                RuleMode::Classic => RuleMode::Colored,
+            }
+        }
+        KeyCode::S => {
+            let message = match save_level(context, &editor.level) {
+                Ok(message) => message,
+                Err(e) => format!("could not save level: {}", e),
+            };
+            drop(editor);
+            refresh(world);
+            world.write_resource::<Editor>().messages.push(message);
+            return true;
+        }
+        KeyCode::L => match open_level(context) {
+            Ok(level) => editor.level = level,
+            Err(e) => {
+                editor.messages = vec![format!("could not open level: {}", e)];
+                return true;
+            }
+        },
+        // nothing changed, no need to respawn the level
+        _ => return true,
+    }
+    drop(editor);
+    refresh(world);
+    true
+}
+
+pub fn handle_mouse_down(world: &mut World, x: f32, y: f32) {
+    {
+        let mut editor = world.write_resource::<Editor>();
+        if !editor.is_editing() {
+            return;
+        }
+        if x >= PANEL_X {
+            let index = ((y - PANEL_Y) / PANEL_STEP).floor();
+            if let Some(brush) = editor.brushes().get(index.max(0.0) as usize) {
+                editor.brush = brush.clone();
+            }
+            return;
+        }
+        editor.painting = true;
+    }
+    handle_mouse_motion(world, x, y);
+}
+
+pub fn handle_mouse_motion(world: &mut World, x: f32, y: f32) {
+    {
+        let mut editor = world.write_resource::<Editor>();
+        if !editor.is_editing() || !editor.painting || x < 0.0 || y < 0.0 {
+            return;
+        }
+        let (x, y) = ((x / TILE_WIDTH) as usize, (y / TILE_WIDTH) as usize);
+        if x >= editor.level.width || y >= editor.level.height {
+            return;
+        }
+        editor.paint(x, y);
+    }
+    refresh(world);
+}
+
+pub fn handle_mouse_up(world: &mut World) {
+    world.write_resource::<Editor>().painting = false;
+}
@@ -5,7 +5,7 @@ pub fn create_wall(world: &mut World, position: Position) {
     world
         .create_entity()
         .with(Position { z: 10, ..position })
-        .with(Renderable::new_static("/images/wall.png".to_string()))
+        .with(Renderable::new(Sprite::Wall))
         .with(Wall {})
         .with(Immovable)
         .build();
@@ -15,7 +15,62 @@ pub fn create_floor(world: &mut World, position: Position) {
     world
         .create_entity()
         .with(Position { z: 5, ..position })
-        .with(Renderable::new_static("/images/floor.png".to_string()))
+        .with(Renderable::new(Sprite::Floor))
+        .build();
+}
+
+pub fn create_ice(world: &mut World, position: Position) {
+    world
+        .create_entity()
+        .with(Position { z: 6, ..position })
+        .with(Renderable::new(Sprite::Ice))
+        .with(Ice)
+        .build();
+}
+
+pub fn create_one_way(world: &mut World, position: Position, direction: Direction) {
+    world
+        .create_entity()
+        .with(Position { z: 6, ..position })
+        .with(Renderable::new(Sprite::OneWay(direction)))
+        .with(OneWay { direction })
+        .build();
+}
+
+pub fn create_teleporter(world: &mut World, position: Position, pair: u8) {
+    world
+        .create_entity()
+        .with(Position { z: 6, ..position })
+        .with(Renderable::new(Sprite::Teleporter))
+        .with(Teleporter { pair })
+        .build();
+}
+
+pub fn create_hole(world: &mut World, position: Position) {
+    world
+        .create_entity()
+        .with(Position { z: 6, ..position })
+        .with(Renderable::new(Sprite::Hole))
+        .with(Hole)
+        .build();
+}
+
+pub fn create_plate(world: &mut World, position: Position, link: u8) {
+    world
+        .create_entity()
+        .with(Position { z: 6, ..position })
+        .with(Renderable::new(Sprite::Plate))
+        .with(Plate { link })
+        .build();
+}
+
+pub fn create_door(world: &mut World, position: Position, link: u8) {
+    world
+        .create_entity()
+        .with(Position { z: 8, ..position })
+        .with(Renderable::new(Sprite::Door { open: false }))
+        .with(Door { link, open: false })
+        .with(Immovable)
         .build();
 }
 
@@ -23,11 +78,7 @@ pub fn create_box(world: &mut World, position: Position, color: BoxColor) {
     world
         .create_entity()
         .with(Position { z: 10, ..position })
-        .with(Renderable::new_static(format!("/images/box_{}.png", color)))
-        .with(Renderable::new_animated(vec![
-            format!("/images/box_{}_1.png", color),
-            format!("/images/box_{}_2.png", color),
-        ]))
+        .with(Renderable::new(Sprite::Box(color.clone())))
         .with(Box { color })
         .with(Movable)
         .build();
@@ -37,24 +88,17 @@ pub fn create_box_spot(world: &mut World, position: Position, color: BoxColor) {
     world
         .create_entity()
         .with(Position { z: 9, ..position })
-        .with(Renderable::new_static(format!(
-            "/images/box_spot_{}.png",
-            color
-        )))
+        .with(Renderable::new(Sprite::BoxSpot(color.clone())))
         .with(BoxSpot { color })
         .build();
 }
 
-pub fn create_player(world: &mut World, position: Position) {
+pub fn create_player(world: &mut World, position: Position, index: u8) {
     world
         .create_entity()
         .with(Position { z: 10, ..position })
-        .with(Renderable::new_animated(vec![
-            "/images/player_1.png".to_string(),
-            "/images/player_2.png".to_string(),
-            "/images/player_3.png".to_string(),
-        ]))
-        .with(Player {})
+        .with(Renderable::new(Sprite::Player(index)))
+        .with(Player { index })
         .with(Movable)
         .build();
 }
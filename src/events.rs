@@ -1,3 +1,6 @@
+use specs::shrev::{EventChannel, EventIterator, ReaderId};
+use std::time::Duration;
+
 pub type EntityId = u32;
 
 #[derive(Debug)]
@@ -13,6 +16,103 @@ pub struct BoxPlacedOnSpot {
 #[derive(Debug)]
 pub enum Event {
     PlayerHitObstacle,
+    // the push would move more boxes than the rules allow
+    PushChainTooLong,
+    // a one-way tile was entered against its direction
+    OneWayBlocked,
+    PlayerStepped,
+    BoxPushed,
+    BoxPulled,
+    IceSlid,
+    Teleported,
+    HoleFilled,
+    DoorOpened,
+    DoorClosed,
     EntityMoved(EntityMoved),
     BoxPlacedOnSpot(BoxPlacedOnSpot),
+    MoveUndone,
+    LevelLoaded,
+    LevelRestarted,
+    LevelCompleted,
+}
+
+impl Event {
+    // Name used to pick the sound of the event in the audio config
+    pub fn sound_name(&self) -> &'static str {
+        match self {
+            Event::PlayerHitObstacle => "player_hit_obstacle",
+            Event::PushChainTooLong => "push_chain_too_long",
+            Event::OneWayBlocked => "one_way_blocked",
+            Event::PlayerStepped => "player_stepped",
+            Event::BoxPushed => "box_pushed",
+            Event::BoxPulled => "box_pulled",
+            Event::IceSlid => "ice_slid",
+            Event::Teleported => "teleported",
+            Event::HoleFilled => "hole_filled",
+            Event::DoorOpened => "door_opened",
+            Event::DoorClosed => "door_closed",
+            Event::EntityMoved(_) => "entity_moved",
+            Event::BoxPlacedOnSpot(BoxPlacedOnSpot {
+                is_correct_spot: true,
+            }) => "box_placed_on_correct_spot",
+            Event::BoxPlacedOnSpot(_) => "box_placed_on_incorrect_spot",
+            Event::MoveUndone => "move_undone",
+            Event::LevelLoaded => "level_loaded",
+            Event::LevelRestarted => "level_restarted",
+            Event::LevelCompleted => "level_completed",
+        }
+    }
+
+    // Whether something moved on the board, for the systems only recomputing then
+    pub fn changes_board(&self) -> bool {
+        matches!(
+            self,
+            Event::EntityMoved(_) | Event::MoveUndone | Event::LevelLoaded
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct TimedEvent {
+    pub frame: u64,
+    // order of the event within its frame
+    pub sequence: u32,
+    pub time: Duration,
+    pub event: Event,
+}
+
+// Events published by the systems, each subscriber reading them in order
+// through its own reader
+#[derive(Default)]
+pub struct EventBus {
+    channel: EventChannel<TimedEvent>,
+    frame: u64,
+    sequence: u32,
+    time: Duration,
+}
+
+impl EventBus {
+    pub fn push(&mut self, event: Event) {
+        self.channel.single_write(TimedEvent {
+            frame: self.frame,
+            sequence: self.sequence,
+            time: self.time,
+            event,
+        });
+        self.sequence += 1;
+    }
+
+    pub fn register_reader(&mut self) -> ReaderId<TimedEvent> {
+        self.channel.register_reader()
+    }
+
+    pub fn read(&self, reader: &mut ReaderId<TimedEvent>) -> EventIterator<'_, TimedEvent> {
+        self.channel.read(reader)
+    }
+
+    pub fn next_frame(&mut self, time: Duration) {
+        self.frame += 1;
+        self.sequence = 0;
+        self.time = time;
+    }
 }
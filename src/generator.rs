@@ -0,0 +1,285 @@
+use log::info;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use specs::{World, WorldExt};
+
+use crate::components::BoxColor;
+use crate::constants::{MAX_LEVEL_HEIGHT, MAX_LEVEL_WIDTH};
+use crate::daily::leave_daily;
+use crate::map::{reset_world, spawn_level, Cell, CurrentLevel, Level, Object, Tile};
+use crate::resources::{Palette, PaletteColor, RuleMode};
+use crate::solver::{solve, Board, State, DIRECTIONS};
+use crate::transform::{orient, Orientation};
+
+const COLORS: [(&str, &str, (u8, u8, u8)); 7] = [
+    ("R", "red", (200, 60, 60)),
+    ("B", "blue", (60, 90, 200)),
+    ("G", "green", (70, 170, 70)),
+    ("Y", "yellow", (220, 200, 60)),
+    ("V", "violet", (150, 80, 190)),
+    ("O", "orange", (230, 140, 50)),
+    ("C", "cyan", (60, 190, 200)),
+];
+
+// Search effort shared by all the attempts, so that generating stays quick,
+// each attempt taking at most a quarter of it
+const MAX_GENERATION_NODES: usize = 50_000;
+const MAX_ATTEMPT_NODES: usize = MAX_GENERATION_NODES / 4;
+
+#[derive(Clone)]
+pub struct GeneratorOptions {
+    pub width: usize,
+    pub height: usize,
+    pub boxes: usize,
+    // no colors generates a classic level
+    pub colors: usize,
+    pub attempts: usize,
+}
+
+impl Default for GeneratorOptions {
+    fn default() -> Self {
+        Self {
+            width: 9,
+            height: 8,
+            boxes: 3,
+            colors: 2,
+            attempts: 20,
+        }
+    }
+}
+
+impl GeneratorOptions {
+    // Parse "WIDTHxHEIGHT,BOXES,COLORS", e.g. "10x8,4,2", the size being capped
+    // to what the window shows
+    pub fn parse(options: &str) -> Option<Self> {
+        let parts: Vec<&str> = options.split(',').collect();
+        let (width, height) = parts.first()?.split_once('x')?;
+        Some(Self {
+            width: width.parse::<usize>().ok()?.min(MAX_LEVEL_WIDTH),
+            height: height.parse::<usize>().ok()?.min(MAX_LEVEL_HEIGHT),
+            boxes: parts.get(1).map_or(Some(3), |b| b.parse().ok())?,
+            colors: parts.get(2).map_or(Some(2), |c| c.parse().ok())?,
+            ..Self::default()
+        })
+    }
+}
+
+pub struct Generated {
+    pub level: Level,
+    pub moves: String,
+    pub pushes: usize,
+    pub nodes: usize,
+}
+
+// Random room surrounded by walls, keeping only its largest connected area
+fn generate_room<R: Rng>(rng: &mut R, width: usize, height: usize) -> Vec<bool> {
+    let mut walls: Vec<bool> = (0..width * height)
+        .map(|i| {
+            let (x, y) = (i % width, i / width);
+            x == 0 || y == 0 || x == width - 1 || y == height - 1 || rng.gen_bool(0.2)
+        })
+        .collect();
+
+    let board = Board {
+        width,
+        height,
+        walls: walls.clone(),
+        spots: vec![None; width * height],
+        classic: true,
+    };
+    let mut best: Vec<bool> = Vec::new();
+    let mut visited = vec![false; width * height];
+    for start in 0..width * height {
+        if walls[start] || visited[start] {
+            continue;
+        }
+        let area = board.reachable(&State::new(Vec::new(), start, width * height));
+        for (cell, reached) in area.iter().enumerate() {
+            visited[cell] |= *reached;
+        }
+        if area.iter().filter(|r| **r).count() > best.iter().filter(|r| **r).count() {
+            best = area;
+        }
+    }
+    for (cell, wall) in walls.iter_mut().enumerate() {
+        *wall = !best.get(cell).copied().unwrap_or(false);
+    }
+    walls
+}
+
+// Pull boxes away from their spots, so that the level is solvable by pushing
+// them back
+fn pull_boxes<R: Rng>(rng: &mut R, board: &Board, state: &mut State, pulls: usize) {
+    for _ in 0..pulls {
+        let reachable = board.reachable(state);
+        let mut candidates = Vec::new();
+        for cell in (0..reachable.len()).filter(|c| reachable[*c]) {
+            for direction in DIRECTIONS.iter() {
+                let (dx, dy, c) = *direction;
+                let the_box = board.step(cell, *direction);
+                let back = board.step(cell, (-dx, -dy, c));
+                if let (Some(the_box), Some(back)) = (the_box, back) {
+                    let free = !board.walls[back] && !state.has_box(back);
+                    if free && state.has_box(the_box) {
+                        candidates.push((cell, the_box, back));
+                    }
+                }
+            }
+        }
+
+        let (cell, the_box, back) = match candidates.choose(rng) {
+            Some(candidate) => *candidate,
+            None => return,
+        };
+        state.move_box(the_box, cell);
+        state.player = back;
+    }
+}
+
+fn palette(colors: usize) -> Palette {
+    let colors = COLORS.iter().take(colors.max(1));
+    Palette {
+        colors: colors
+            .map(|(key, name, rgb)| PaletteColor {
+                key: key.to_string(),
+                name: name.to_string(),
+                rgb: *rgb,
+            })
+            .collect(),
+    }
+}
+
+fn attempt<R: Rng>(rng: &mut R, options: &GeneratorOptions) -> Option<Level> {
+    let (width, height) = (options.width, options.height);
+    let walls = generate_room(rng, width, height);
+    let mut floors: Vec<usize> = (0..walls.len()).filter(|c| !walls[*c]).collect();
+    if floors.len() < options.boxes * 2 + 2 {
+        return None;
+    }
+    floors.shuffle(rng);
+
+    let colors = options.colors.min(COLORS.len());
+    let color = |i: usize| {
+        if colors == 0 {
+            0
+        } else {
+            (i % colors) as u8 + 1
+        }
+    };
+    let goals: Vec<(usize, u8)> = (0..options.boxes).map(|i| (floors[i], color(i))).collect();
+    let mut spots = vec![None; walls.len()];
+    for (cell, color) in goals.iter() {
+        spots[*cell] = Some(*color);
+    }
+    let board = Board {
+        width,
+        height,
+        walls,
+        spots,
+        classic: colors == 0,
+    };
+
+    let mut state = State::new(goals, floors[options.boxes], width * height);
+    pull_boxes(rng, &board, &mut state, width * height * 2);
+    if board.is_solved(&state) {
+        return None;
+    }
+
+    let palette = palette(colors);
+    let box_color = |color: u8| match color {
+        0 => BoxColor::Neutral,
+        c => BoxColor::Colored(palette.colors[c as usize - 1].name.clone()),
+    };
+    let mut level = Level::new(width, height, palette.clone());
+    if colors == 0 {
+        level.rule_mode = RuleMode::Classic;
+    }
+    for cell in 0..board.walls.len() {
+        let (x, y) = (cell % width, cell / width);
+        *level.cell_mut(x, y) = Cell {
+            tile: if board.walls[cell] {
+                Tile::Wall
+            } else {
+                Tile::Floor
+            },
+            spot: board.spots[cell].map(box_color),
+            object: None,
+        };
+    }
+    for (cell, color) in state.boxes.iter() {
+        level.cell_mut(cell % width, cell / width).object = Some(Object::Box(box_color(*color)));
+    }
+    level
+        .cell_mut(state.player % width, state.player / width)
+        .object = Some(Object::Player);
+    Some(level)
+}
+
+// Generate several puzzles and keep the one with the longest solution, the
+// search effort breaking ties
+pub fn generate<R: Rng>(rng: &mut R, options: &GeneratorOptions) -> Option<Generated> {
+    let mut best: Option<Generated> = None;
+    let mut budget = MAX_GENERATION_NODES;
+    for _ in 0..options.attempts.max(1) {
+        if budget == 0 {
+            break;
+        }
+        let level = match attempt(rng, options) {
+            Some(level) => level,
+            None => continue,
+        };
+        let search = solve(&level, MAX_ATTEMPT_NODES.min(budget));
+        budget = budget.saturating_sub(search.nodes);
+        let solution = match search.solution {
+            Some(solution) => solution,
+            None => continue,
+        };
+        let score = (solution.pushes, search.nodes);
+        if best.as_ref().is_none_or(|b| score > (b.pushes, b.nodes)) {
+            best = Some(Generated {
+                level,
+                moves: solution.moves,
+                pushes: solution.pushes,
+                nodes: search.nodes,
+            });
+        }
+    }
+    best
+}
+
+// Endless mode where a new random level follows each solved one
+#[derive(Default)]
+pub struct RandomLevels {
+    pub active: bool,
+    pub options: GeneratorOptions,
+    pub generated: usize,
+}
+
+pub fn next_random_level(world: &mut World) {
+    let (options, generated_count) = {
+        let mut random_levels = world.write_resource::<RandomLevels>();
+        random_levels.active = true;
+        random_levels.generated += 1;
+        (random_levels.options.clone(), random_levels.generated)
+    };
+    leave_daily(world);
+    if let Some(generated) = generate(&mut rand::thread_rng(), &options) {
+        let level = orient(world, generated.level);
+        let moves = world
+            .read_resource::<Orientation>()
+            .transform
+            .moves(&generated.moves);
+        info!(
+            "Random level {} ({} pushes, solution {}):\n{}",
+            generated_count, generated.pushes, moves, level
+        );
+        reset_world(world);
+        spawn_level(world, level);
+    } else {
+        let error = format!(
+            "could not generate a {}x{} level with {} boxes",
+            options.width, options.height, options.boxes
+        );
+        world.write_resource::<CurrentLevel>().errors = vec![error];
+    }
+}
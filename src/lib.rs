@@ -0,0 +1,18 @@
+pub mod audio;
+pub mod components;
+pub mod constants;
+pub mod daily;
+pub mod difficulty;
+pub mod editor;
+pub mod entities;
+pub mod events;
+pub mod generator;
+pub mod logging;
+pub mod map;
+pub mod music;
+pub mod normalize;
+pub mod resources;
+pub mod solver;
+pub mod systems;
+pub mod theme;
+pub mod transform;
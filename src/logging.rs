@@ -0,0 +1,41 @@
+use log::{LevelFilter, Log, Metadata, Record};
+
+// Writes log records to stderr, other crates only showing warnings and errors
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.target().starts_with("sokoban") || metadata.level() <= log::Level::Warn
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!(
+                "[{:<5} {}] {}",
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: Logger = Logger;
+
+// Level from "--log=LEVEL", then the SOKOBAN_LOG variable, info by default
+pub fn level(args: &[String]) -> LevelFilter {
+    args.iter()
+        .find_map(|arg| arg.strip_prefix("--log="))
+        .map(str::to_string)
+        .or_else(|| std::env::var("SOKOBAN_LOG").ok())
+        .and_then(|level| level.parse().ok())
+        .unwrap_or(LevelFilter::Info)
+}
+
+pub fn init(level: LevelFilter) {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
@@ -1,111 +1,279 @@
 use ggez::event::{self, EventHandler};
-use ggez::event::{KeyCode, KeyMods};
+use ggez::event::{KeyCode, KeyMods, MouseButton};
 use ggez::{conf, timer, Context, ContextBuilder, GameError, GameResult};
-use specs::{RunNow, World, WorldExt};
+use log::warn;
+use specs::{Dispatcher, RunNow, World, WorldExt};
 use std::path;
 
-mod audio;
-mod components;
-mod constants;
-mod entities;
-mod events;
-mod map;
-mod resources;
-mod systems;
-
-use crate::audio::*;
-use crate::components::*;
-use crate::map::*;
-use crate::resources::*;
-use crate::systems::*;
+use sokoban::audio::*;
+use sokoban::components::*;
+use sokoban::daily::*;
+use sokoban::difficulty;
+use sokoban::editor;
+use sokoban::events::EventBus;
+use sokoban::generator::*;
+use sokoban::logging;
+use sokoban::map::*;
+use sokoban::music::*;
+use sokoban::normalize;
+use sokoban::resources::*;
+use sokoban::systems::*;
+use sokoban::theme::*;
+use sokoban::transform::{self, Orientation, Transform};
 
 // Game hold all the game state
 struct Game {
     world: World,
+    dispatcher: Dispatcher<'static, 'static>,
 }
 
 impl Game {
-    pub fn new(world: World) -> Game {
-        Game { world }
+    // Subscribers only see the events published once they are set up
+    pub fn new(mut world: World) -> Game {
+        let mut dispatcher = dispatcher();
+        dispatcher.setup(&mut world);
+        Game { world, dispatcher }
     }
 }
 
 impl EventHandler<GameError> for Game {
     fn update(&mut self, context: &mut Context) -> GameResult {
-        {
-            let mut is = InputSystem {};
-            is.run_now(&self.world);
-        }
-        {
-            let mut gss = GameplayStateSystem {};
-            gss.run_now(&self.world);
-        }
         {
             let mut time = self.world.write_resource::<Time>();
             time.delta += timer::delta(context);
+            self.world
+                .write_resource::<EventBus>()
+                .next_frame(time.delta);
         }
-
-        {
-            let mut es = EventSystem { context };
-            es.run_now(&self.world);
-        }
+        self.dispatcher.dispatch(&self.world);
+        self.world.maintain();
+        update_audio(&mut self.world, context);
+        update_daily(&mut self.world, context);
+        update_music(&mut self.world, context);
         Ok(())
     }
 
     fn draw(&mut self, context: &mut Context) -> GameResult<()> {
-        {
-            let mut rs = RenderingSystem { context };
-            rs.run_now(&self.world);
-        }
+        Timed::new("rendering", RenderingSystem { context }).run_now(&self.world);
         Ok(())
     }
 
     fn key_down_event(
         &mut self,
-        _context: &mut Context,
+        context: &mut Context,
         keycode: KeyCode,
-        _keymod: KeyMods,
+        keymod: KeyMods,
         _repeat: bool,
     ) {
+        if keycode == KeyCode::F3 {
+            let mut debug_info = self.world.write_resource::<DebugInfo>();
+            debug_info.visible = !debug_info.visible;
+            return;
+        }
+        if keycode == KeyCode::T {
+            self.world.write_resource::<Themes>().next();
+            return;
+        }
+        if handle_key(&mut self.world, context, keycode, keymod) {
+            return;
+        }
+        if editor::handle_key(&mut self.world, context, keycode) {
+            return;
+        }
+        if transform::handle_key(&mut self.world, keycode) {
+            return;
+        }
+        let won = self.world.read_resource::<Gameplay>().state == GameplayState::Won;
+        let random = self.world.read_resource::<RandomLevels>().active;
+        if keycode == KeyCode::Back {
+            restart_level(&mut self.world);
+            return;
+        }
+        if keycode == KeyCode::F6 {
+            start_daily(&mut self.world);
+            return;
+        }
+        if keycode == KeyCode::F5 || (keycode == KeyCode::Return && won && random) {
+            next_random_level(&mut self.world);
+            return;
+        }
+
         let mut input_queue = self.world.write_resource::<InputQueue>();
         input_queue.keys_pressed.push(keycode);
     }
+
+    fn mouse_button_down_event(
+        &mut self,
+        _context: &mut Context,
+        button: MouseButton,
+        x: f32,
+        y: f32,
+    ) {
+        if button == MouseButton::Left {
+            editor::handle_mouse_down(&mut self.world, x, y);
+        }
+    }
+
+    fn mouse_button_up_event(
+        &mut self,
+        _context: &mut Context,
+        _button: MouseButton,
+        _x: f32,
+        _y: f32,
+    ) {
+        editor::handle_mouse_up(&mut self.world);
+    }
+
+    fn mouse_motion_event(&mut self, _context: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) {
+        editor::handle_mouse_motion(&mut self.world, x, y);
+    }
 }
 
-pub fn initialize_level(world: &mut World) {
+pub fn initialize_level(world: &mut World, path: Option<String>) {
     const MAP: &str = "
+    ; color R red 200 60 60
+    ; color B blue 60 90 200
+    ; color G green 70 170 70
     N N W W W W W W
     W W W . . . . W
     W . . . BB . . W
-    W . . RB . . . W 
+    W . . RB . . GS W
     W . P . . . . W
     W W W . W RS . W
-    W . . BS . . . W
+    W . . BS . GB . W
     W . . . . . . W
     W W W W W W W W
     ";
 
-    load_map(world, MAP.to_string());
+    let map_string = match path {
+        Some(path) => match std::fs::read_to_string(&path) {
+            Ok(map_string) => map_string,
+            Err(e) => {
+                let error = format!("could not read {}: {}", path, e);
+                world.write_resource::<CurrentLevel>().errors = vec![error];
+                return;
+            }
+        },
+        None => MAP.to_string(),
+    };
+    leave_daily(world);
+
+    // Keep the game running and show what is wrong with the level
+    if let Err(errors) = load_map(world, map_string) {
+        world.write_resource::<CurrentLevel>().errors =
+            errors.iter().map(|e| e.to_string()).collect();
+    }
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    logging::init(logging::level(&args));
+    // "sokoban difficulty LEVEL..." rates level files and "sokoban dedupe LEVEL..."
+    // finds duplicates among them, without starting the game
+    let command = match args.first().map(String::as_str) {
+        Some("difficulty") => Some(difficulty::run_cli as fn(&[String]) -> Result<(), String>),
+        Some("dedupe") => Some(normalize::run_cli as fn(&[String]) -> Result<(), String>),
+        _ => None,
+    };
+    if let Some(command) = command {
+        if let Err(e) = command(&args[1..]) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let mut world = World::new();
     register_components(&mut world);
     register_resources(&mut world);
-    initialize_level(&mut world);
+    let mut game = Game::new(world);
+    let world = &mut game.world;
+    if args.iter().any(|arg| arg == "--classic") {
+        world.write_resource::<Rules>().global_mode = Some(RuleMode::Classic);
+    }
+    if args.iter().any(|arg| arg == "--pull") {
+        world.write_resource::<Rules>().move_rule = MoveRule::Pull;
+    }
+    if let Some(max) = args.iter().find_map(|arg| arg.strip_prefix("--max-push=")) {
+        match max.parse::<usize>() {
+            Ok(max) if max > 0 => world.write_resource::<Rules>().max_push_chain = Some(max),
+            _ => {
+                eprintln!(
+                    "Invalid --max-push value {}, expected a positive number",
+                    max
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(transform) = args.iter().find_map(|arg| arg.strip_prefix("--transform=")) {
+        match Transform::parse(transform) {
+            Ok(transform) => world.write_resource::<Orientation>().transform = transform,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    let random = args.iter().find(|arg| arg.starts_with("--random"));
+    if args.iter().any(|arg| arg == "--daily") {
+        start_daily(world);
+    } else if let Some(random) = random {
+        if let Some(options) = random.strip_prefix("--random=") {
+            match GeneratorOptions::parse(options) {
+                Some(options) => world.write_resource::<RandomLevels>().options = options,
+                None => {
+                    eprintln!(
+                        "Invalid --random value {}, expected WIDTHxHEIGHT[,BOXES[,COLORS]]",
+                        options
+                    );
+                    std::process::exit(1);
+                }
+            }
+        }
+        next_random_level(world);
+    } else {
+        let level_path = args.iter().find(|arg| !arg.starts_with("--")).cloned();
+        initialize_level(world, level_path);
+    }
 
     // create a game context and event loop
-    let context_builder = ContextBuilder::new("babidiii_sokoban", "sokoban")
-        .window_setup(conf::WindowSetup::default().title("Sokoban!"))
-        .window_mode(conf::WindowMode::default().dimensions(1000.0, 600.0))
-        .add_resource_path(path::PathBuf::from("./resources"));
+    let context_builder = |audio| {
+        ContextBuilder::new("babidiii_sokoban", "sokoban")
+            .window_setup(conf::WindowSetup::default().title("Sokoban!"))
+            .window_mode(conf::WindowMode::default().dimensions(1000.0, 600.0))
+            .modules(conf::ModuleConf::default().audio(audio))
+            .add_resource_path(path::PathBuf::from("./resources"))
+    };
 
-    let (mut ctx, event_loop) = context_builder.build().expect("Could not create ggez game");
-    initialize_sounds(&mut world, &mut ctx);
-
-    // Create game state
-    let game = Game::new(world);
-    // let game = &mut Game {};
+    // Play without sound rather than not at all when there is no audio device
+    let no_audio = args.iter().any(|arg| arg == "--no-audio");
+    let built = if no_audio {
+        Err(GameError::AudioError(
+            "disabled with --no-audio".to_string(),
+        ))
+    } else {
+        context_builder(true).build()
+    };
+    let (mut ctx, event_loop) = match built {
+        Ok(built) => {
+            world
+                .write_resource::<AudioStore>()
+                .set_backend(GgezBackend::default());
+            built
+        }
+        Err(GameError::AudioError(e)) => {
+            warn!("Audio disabled: {}", e);
+            context_builder(false)
+                .build()
+                .expect("Could not create ggez game")
+        }
+        Err(e) => panic!("Could not create ggez game: {}", e),
+    };
+    initialize_sounds(world, &mut ctx);
+    initialize_music(world, &mut ctx);
+    initialize_themes(world, &mut ctx);
+    load_record(world, &mut ctx);
 
     event::run(ctx, event_loop, game)
 }
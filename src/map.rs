@@ -1,53 +1,807 @@
-use crate::components::{BoxColor, Position};
+use crate::components::{BoxColor, Direction, Position};
+use crate::constants::MAX_PLAYERS;
 use crate::entities::*;
-use specs::World;
+use crate::events::{Event, EventBus};
+use crate::resources::{Gameplay, InputQueue, MoveHistory, Palette, PaletteColor, RuleMode, Rules};
+use crate::transform::orient;
+use specs::{World, WorldExt};
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Display;
 
-pub fn load_map(world: &mut World, map_string: String) {
-    // read all lines
-    let rows: Vec<&str> = map_string.trim().split('\n').map(|x| x.trim()).collect();
+// Entity positions are stored on a u8 per axis
+const MAX_SIZE: usize = u8::MAX as usize + 1;
 
-    for (y, row) in rows.iter().enumerate() {
-        let columns: Vec<&str> = row.split(' ').collect();
+#[derive(Clone, Copy, PartialEq)]
+pub enum Tile {
+    Nothing,
+    Floor,
+    Wall,
+    // Boxes and players keep sliding on it until blocked
+    Ice,
+    // Can only be entered moving in its direction
+    OneWay(Direction),
+    // Sends what enters it to the other teleporter of the same pair
+    Teleporter(u8),
+    // Swallows the first box pushed into it, becoming floor, players can't enter it
+    Hole,
+    // Opens the doors of its link while a box or a player stands on it
+    Plate(u8),
+    Door(u8),
+}
 
-        for (x, column) in columns.iter().enumerate() {
-            // Create the position at which to create something on the map
-            let position = Position {
-                x: x as u8,
-                y: y as u8,
-                z: 0, // we will get the z from the factory functions
-            };
+#[derive(Clone, PartialEq)]
+pub enum Object {
+    Player,
+    Box(BoxColor),
+}
+
+#[derive(Clone)]
+pub struct Cell {
+    pub tile: Tile,
+    pub spot: Option<BoxColor>,
+    pub object: Option<Object>,
+}
+
+impl Cell {
+    pub fn new(tile: Tile) -> Self {
+        Self {
+            tile,
+            spot: None,
+            object: None,
+        }
+    }
+}
 
-            // Figure out what object we should create
-            match *column {
-                "." => create_floor(world, position),
-                "W" => {
-                    create_floor(world, position);
-                    create_wall(world, position);
+// Grid representation of a level, as read from and written to the map format
+#[derive(Clone)]
+pub struct Level {
+    pub palette: Palette,
+    pub rule_mode: RuleMode,
+    pub width: usize,
+    pub height: usize,
+    cells: Vec<Cell>,
+    // line of each row in the parsed map text
+    lines: Vec<usize>,
+}
+
+impl Default for Level {
+    fn default() -> Self {
+        Self::new(0, 0, Palette::default())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum MapErrorKind {
+    UnrecognizedItem(String),
+    UnrecognizedColor(String),
+    InvalidDirective(String),
+    PlayerCount(usize),
+    NotEnclosed,
+    BoxCount { boxes: usize, spots: usize },
+    TooManyBoxes(BoxColor),
+    NotEnoughBoxes(BoxColor),
+    UnreachableBox,
+    UnreachableSpot,
+    UnpairedTeleporter(u8),
+    UnmatchedLink(u8),
+    TooLarge,
+}
+
+impl Display for MapErrorKind {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapErrorKind::UnrecognizedItem(token) => write!(fmt, "unrecognized map item {}", token),
+            MapErrorKind::UnrecognizedColor(key) => write!(fmt, "unrecognized box color {}", key),
+            MapErrorKind::InvalidDirective(line) => write!(fmt, "invalid directive {}", line),
+            MapErrorKind::PlayerCount(count) => {
+                write!(
+                    fmt,
+                    "expected 1 to {} players, found {}",
+                    MAX_PLAYERS, count
+                )
+            }
+            MapErrorKind::NotEnclosed => write!(fmt, "level is not enclosed by walls"),
+            MapErrorKind::BoxCount { boxes, spots } => {
+                write!(fmt, "{} boxes for {} spots", boxes, spots)
+            }
+            MapErrorKind::TooManyBoxes(color) => write!(fmt, "too many {} boxes", color),
+            MapErrorKind::NotEnoughBoxes(color) => write!(fmt, "not enough {} boxes", color),
+            MapErrorKind::UnreachableBox => write!(fmt, "box out of the player's reach"),
+            MapErrorKind::UnreachableSpot => write!(fmt, "spot out of the player's reach"),
+            MapErrorKind::UnpairedTeleporter(pair) => {
+                write!(fmt, "teleporter T{} must appear exactly twice", pair)
+            }
+            MapErrorKind::TooLarge => {
+                write!(fmt, "level larger than {} by {} items", MAX_SIZE, MAX_SIZE)
+            }
+            MapErrorKind::UnmatchedLink(link) => {
+                write!(
+                    fmt,
+                    "link {} needs both plates (_{}) and doors (D{})",
+                    link, link, link
+                )
+            }
+        }
+    }
+}
+
+// Problem found in a level, located by its line in the map text and its column
+// counted in map items
+#[derive(Clone, Debug)]
+pub struct MapError {
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub kind: MapErrorKind,
+}
+
+impl Display for MapError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(fmt, "{}:{}: {}", line, column, self.kind),
+            (Some(line), None) => write!(fmt, "{}: {}", line, self.kind),
+            _ => write!(fmt, "{}", self.kind),
+        }
+    }
+}
+
+// Directive lines start with ';', e.g. "; color G green 70 170 70"
+fn parse_directive(
+    line: usize,
+    directive: &str,
+    colors: &mut Vec<PaletteColor>,
+    rule_mode: &mut RuleMode,
+) -> Result<(), MapError> {
+    let words: Vec<&str> = directive.split_whitespace().collect();
+    let invalid = || MapError {
+        line: Some(line),
+        column: None,
+        kind: MapErrorKind::InvalidDirective(directive.to_string()),
+    };
+
+    match words.as_slice() {
+        ["color", key, name, r, g, b] => {
+            let component = |c: &str| c.parse::<u8>().map_err(|_| invalid());
+            colors.push(PaletteColor {
+                key: key.to_string(),
+                name: name.to_string(),
+                rgb: (component(r)?, component(g)?, component(b)?),
+            });
+        }
+        // "; rules classic" makes the level ignore box colors
+        ["rules", "classic"] => *rule_mode = RuleMode::Classic,
+        ["rules", "colored"] => *rule_mode = RuleMode::Colored,
+        ["color", ..] | ["rules", ..] => return Err(invalid()),
+        // anything else is a comment
+        _ => (),
+    }
+    Ok(())
+}
+
+fn parse_cell(token: &str, palette: &Palette) -> Result<Cell, MapErrorKind> {
+    // A color key followed by B is a box, S a spot, * a box on its spot and
+    // + the player on a spot, no key is neutral
+    let color = |key: &str| -> Result<BoxColor, MapErrorKind> {
+        if key.is_empty() {
+            Ok(BoxColor::Neutral)
+        } else {
+            palette
+                .color(key)
+                .ok_or_else(|| MapErrorKind::UnrecognizedColor(key.to_string()))
+        }
+    };
+    // split on the last character, which may take several bytes
+    let (key, suffix) = match token.char_indices().last() {
+        Some((index, _)) => token.split_at(index),
+        None => ("", ""),
+    };
+    let floor = Cell::new(Tile::Floor);
+    // teleporters come in pairs, e.g. T0 twice, and plates _0 open the doors D0
+    if let Some(Ok(pair)) = token.strip_prefix('T').map(str::parse::<u8>) {
+        return Ok(Cell::new(Tile::Teleporter(pair)));
+    }
+    if let Some(Ok(link)) = token.strip_prefix('_').map(str::parse::<u8>) {
+        return Ok(Cell::new(Tile::Plate(link)));
+    }
+    if let Some(Ok(link)) = token.strip_prefix('D').map(str::parse::<u8>) {
+        return Ok(Cell::new(Tile::Door(link)));
+    }
+
+    let cell = match token {
+        "." => floor,
+        "W" => Cell::new(Tile::Wall),
+        "P" => Cell {
+            object: Some(Object::Player),
+            ..floor
+        },
+        "N" => Cell::new(Tile::Nothing),
+        "I" => Cell::new(Tile::Ice),
+        "H" => Cell::new(Tile::Hole),
+        "^" => Cell::new(Tile::OneWay(Direction::Up)),
+        "v" => Cell::new(Tile::OneWay(Direction::Down)),
+        "<" => Cell::new(Tile::OneWay(Direction::Left)),
+        ">" => Cell::new(Tile::OneWay(Direction::Right)),
+        _ => match suffix {
+            "B" => Cell {
+                object: Some(Object::Box(color(key)?)),
+                ..floor
+            },
+            "S" => Cell {
+                spot: Some(color(key)?),
+                ..floor
+            },
+            "*" => Cell {
+                spot: Some(color(key)?),
+                object: Some(Object::Box(color(key)?)),
+                ..floor
+            },
+            "+" => Cell {
+                spot: Some(color(key)?),
+                object: Some(Object::Player),
+                ..floor
+            },
+            _ => return Err(MapErrorKind::UnrecognizedItem(token.to_string())),
+        },
+    };
+    Ok(cell)
+}
+
+impl Level {
+    pub fn new(width: usize, height: usize, palette: Palette) -> Self {
+        Self {
+            palette,
+            rule_mode: RuleMode::Colored,
+            width,
+            height,
+            cells: vec![Cell::new(Tile::Nothing); width * height],
+            lines: Vec::new(),
+        }
+    }
+
+    pub fn parse(map_string: &str) -> Result<Self, Vec<MapError>> {
+        let mut errors = Vec::new();
+        // read all lines with their line number, directives apart from map rows
+        let (directives, rows): (Vec<_>, Vec<_>) = map_string
+            .split('\n')
+            .enumerate()
+            .map(|(i, x)| (i + 1, x.trim()))
+            .filter(|(_, x)| !x.is_empty())
+            .partition(|(_, x): &(usize, &str)| x.starts_with(';'));
+
+        let mut colors = Vec::new();
+        let mut rule_mode = RuleMode::Colored;
+        for (line, directive) in directives.iter() {
+            if let Err(e) =
+                parse_directive(*line, directive[1..].trim(), &mut colors, &mut rule_mode)
+            {
+                errors.push(e);
+            }
+        }
+        let palette = if colors.is_empty() {
+            Palette::default()
+        } else {
+            Palette { colors }
+        };
+
+        let mut cells: Vec<Vec<Cell>> = Vec::new();
+        for (line, row) in rows.iter() {
+            let mut row_cells = Vec::new();
+            for (column, token) in row.split_whitespace().enumerate() {
+                match parse_cell(token, &palette) {
+                    Ok(cell) => row_cells.push(cell),
+                    Err(kind) => {
+                        errors.push(MapError {
+                            line: Some(*line),
+                            column: Some(column + 1),
+                            kind,
+                        });
+                        row_cells.push(Cell::new(Tile::Nothing));
+                    }
                 }
-                "P" => {
-                    create_floor(world, position);
-                    create_player(world, position);
+            }
+            cells.push(row_cells);
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        let width = cells.iter().map(|row| row.len()).max().unwrap_or(0);
+
+        let mut level = Level::new(width, cells.len(), palette);
+        level.rule_mode = rule_mode;
+        level.lines = rows.iter().map(|(line, _)| *line).collect();
+        for (y, row) in cells.into_iter().enumerate() {
+            for (x, cell) in row.into_iter().enumerate() {
+                *level.cell_mut(x, y) = cell;
+            }
+        }
+        Ok(level)
+    }
+
+    fn error(&self, x: usize, y: usize, kind: MapErrorKind) -> MapError {
+        MapError {
+            line: Some(self.lines.get(y).copied().unwrap_or(y + 1)),
+            column: Some(x + 1),
+            kind,
+        }
+    }
+
+    // Cells the player can walk to when ignoring boxes, and the first opening
+    // to the outside of the map if there is one
+    pub fn reachable(&self, from: (usize, usize)) -> (Vec<bool>, Option<(usize, usize)>) {
+        let mut visited = vec![false; self.width * self.height];
+        let mut opening = None;
+        let mut stack = vec![from];
+        visited[from.1 * self.width + from.0] = true;
+
+        while let Some((x, y)) = stack.pop() {
+            if let Some((px, py)) = self.teleporter_partner(x, y) {
+                if !visited[py * self.width + px] {
+                    visited[py * self.width + px] = true;
+                    stack.push((px, py));
                 }
-                "RB" => {
-                    create_floor(world, position);
-                    create_box(world, position, BoxColor::Red);
+            }
+            let neighbours = [
+                (x.checked_sub(1), Some(y)),
+                (Some(x + 1).filter(|x| *x < self.width), Some(y)),
+                (Some(x), y.checked_sub(1)),
+                (Some(x), Some(y + 1).filter(|y| *y < self.height)),
+            ];
+            for neighbour in neighbours.iter() {
+                let (nx, ny) = match neighbour {
+                    (Some(nx), Some(ny)) => (*nx, *ny),
+                    _ => {
+                        opening.get_or_insert((x, y));
+                        continue;
+                    }
+                };
+                match self.cell(nx, ny).tile {
+                    Tile::Wall => (),
+                    Tile::Nothing => {
+                        opening.get_or_insert((x, y));
+                    }
+                    _ if !visited[ny * self.width + nx] => {
+                        visited[ny * self.width + nx] = true;
+                        stack.push((nx, ny));
+                    }
+                    _ => (),
                 }
+            }
+        }
+        (visited, opening)
+    }
+
+    // Cell of the other teleporter of the pair of the one at a cell
+    pub fn teleporter_partner(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        let pair = match self.cell(x, y).tile {
+            Tile::Teleporter(pair) => pair,
+            _ => return None,
+        };
+        (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .find(|cell| {
+                *cell != (x, y) && self.cell(cell.0, cell.1).tile == Tile::Teleporter(pair)
+            })
+    }
+
+    // Whether the level uses tiles past walls and floors, which the solver
+    // doesn't know
+    pub fn has_special_tiles(&self) -> bool {
+        self.cells
+            .iter()
+            .any(|cell| !matches!(cell.tile, Tile::Nothing | Tile::Floor | Tile::Wall))
+    }
 
-                "BB" => {
-                    create_floor(world, position);
-                    create_box(world, position, BoxColor::Blue);
+    // Cells of the players, in reading order
+    pub fn players(&self) -> Vec<(usize, usize)> {
+        (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .filter(|(x, y)| self.cell(*x, *y).object == Some(Object::Player))
+            .collect()
+    }
+
+    pub fn validate(&self) -> Vec<MapError> {
+        let mut errors = Vec::new();
+        // located on the first item out of range
+        let out_of_range = (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .filter(|(x, y)| *x >= MAX_SIZE || *y >= MAX_SIZE)
+            .find(|(x, y)| self.cell(*x, *y).tile != Tile::Nothing);
+        if let Some((x, y)) = out_of_range {
+            errors.push(self.error(x, y, MapErrorKind::TooLarge));
+        }
+        let players = self.players();
+        let mut boxes: HashMap<BoxColor, usize> = HashMap::new();
+        let mut spots: HashMap<BoxColor, usize> = HashMap::new();
+        let mut holes = 0;
+        let mut teleporters: HashMap<u8, usize> = HashMap::new();
+        // plates and doors count of each link
+        let mut links: HashMap<u8, (usize, usize)> = HashMap::new();
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let cell = self.cell(x, y);
+                match cell.tile {
+                    Tile::Hole => holes += 1,
+                    Tile::Teleporter(pair) => *teleporters.entry(pair).or_default() += 1,
+                    Tile::Plate(link) => links.entry(link).or_default().0 += 1,
+                    Tile::Door(link) => links.entry(link).or_default().1 += 1,
+                    _ => (),
                 }
-                "BS" => {
-                    create_floor(world, position);
-                    create_box_spot(world, position, BoxColor::Blue);
+                if let Some(Object::Box(color)) = &cell.object {
+                    *boxes.entry(color.clone()).or_default() += 1;
                 }
-                "RS" => {
-                    create_floor(world, position);
-                    create_box_spot(world, position, BoxColor::Red);
+                if let Some(color) = &cell.spot {
+                    *spots.entry(color.clone()).or_default() += 1;
                 }
-                "N" => (),
-                c => panic!("unrecognized map item {}", c),
             }
         }
+
+        let no_location = |kind| MapError {
+            line: None,
+            column: None,
+            kind,
+        };
+        if (1..=MAX_PLAYERS).contains(&players.len()) {
+            // boxes and spots only need to be in reach of one of the players
+            let mut reachable = vec![false; self.width * self.height];
+            for player in players {
+                let (reached, opening) = self.reachable(player);
+                if let Some((x, y)) = opening {
+                    errors.push(self.error(x, y, MapErrorKind::NotEnclosed));
+                }
+                for (cell, reached) in reachable.iter_mut().zip(reached) {
+                    *cell |= reached;
+                }
+            }
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    let cell = self.cell(x, y);
+                    if reachable[y * self.width + x] || cell.tile != Tile::Floor {
+                        continue;
+                    }
+                    if let Some(Object::Box(_)) = cell.object {
+                        errors.push(self.error(x, y, MapErrorKind::UnreachableBox));
+                    }
+                    if cell.spot.is_some() {
+                        errors.push(self.error(x, y, MapErrorKind::UnreachableSpot));
+                    }
+                }
+            }
+        } else {
+            errors.push(no_location(MapErrorKind::PlayerCount(players.len())));
+        }
+
+        let mut unpaired: Vec<u8> = teleporters
+            .into_iter()
+            .filter(|(_, count)| *count != 2)
+            .map(|(pair, _)| pair)
+            .collect();
+        unpaired.sort_unstable();
+        for pair in unpaired {
+            errors.push(no_location(MapErrorKind::UnpairedTeleporter(pair)));
+        }
+        let mut unmatched: Vec<u8> = links
+            .into_iter()
+            .filter(|(_, (plates, doors))| *plates == 0 || *doors == 0)
+            .map(|(link, _)| link)
+            .collect();
+        unmatched.sort_unstable();
+        for link in unmatched {
+            errors.push(no_location(MapErrorKind::UnmatchedLink(link)));
+        }
+
+        // Holes may swallow the boxes left over once the spots are filled, and a
+        // level without spots would be won from the start
+        let boxes_count: usize = boxes.values().sum();
+        let spots_count: usize = spots.values().sum();
+        if spots_count == 0 || boxes_count < spots_count || boxes_count > spots_count + holes {
+            errors.push(no_location(MapErrorKind::BoxCount {
+                boxes: boxes_count,
+                spots: spots_count,
+            }));
+        }
+        if self.rule_mode == RuleMode::Classic {
+            return errors;
+        }
+
+        // Neutral boxes and spots can make up for a colored one
+        let count = |counts: &HashMap<BoxColor, usize>, color: &BoxColor| {
+            counts.get(color).copied().unwrap_or(0)
+        };
+        let neutral_boxes = count(&boxes, &BoxColor::Neutral);
+        let neutral_spots = count(&spots, &BoxColor::Neutral);
+        for color in self.palette.colors.iter() {
+            let color = BoxColor::Colored(color.name.clone());
+            let (color_boxes, color_spots) = (count(&boxes, &color), count(&spots, &color));
+            if color_boxes > color_spots + neutral_spots + holes {
+                errors.push(no_location(MapErrorKind::TooManyBoxes(color.clone())));
+            }
+            if color_spots > color_boxes + neutral_boxes {
+                errors.push(no_location(MapErrorKind::NotEnoughBoxes(color)));
+            }
+        }
+        errors
+    }
+
+    pub fn cell(&self, x: usize, y: usize) -> &Cell {
+        &self.cells[y * self.width + x]
+    }
+
+    pub fn cell_mut(&mut self, x: usize, y: usize) -> &mut Cell {
+        &mut self.cells[y * self.width + x]
+    }
+
+    // Resize the grid, keeping the cells from the top left corner
+    pub fn resize(&mut self, width: usize, height: usize) {
+        let mut resized = Level::new(width, height, self.palette.clone());
+        resized.rule_mode = self.rule_mode;
+        for y in 0..height.min(self.height) {
+            for x in 0..width.min(self.width) {
+                *resized.cell_mut(x, y) = self.cell(x, y).clone();
+            }
+        }
+        *self = resized;
+    }
+
+    fn token(&self, cell: &Cell) -> String {
+        let key = |color: &BoxColor| self.palette.key(color).unwrap_or("").to_string();
+
+        match (cell.tile, &cell.object, &cell.spot) {
+            (Tile::Nothing, _, _) => "N".to_string(),
+            (Tile::Wall, _, _) => "W".to_string(),
+            (Tile::Ice, _, _) => "I".to_string(),
+            (Tile::Hole, _, _) => "H".to_string(),
+            (Tile::OneWay(Direction::Up), _, _) => "^".to_string(),
+            (Tile::OneWay(Direction::Down), _, _) => "v".to_string(),
+            (Tile::OneWay(Direction::Left), _, _) => "<".to_string(),
+            (Tile::OneWay(Direction::Right), _, _) => ">".to_string(),
+            (Tile::Teleporter(pair), _, _) => format!("T{}", pair),
+            (Tile::Plate(link), _, _) => format!("_{}", link),
+            (Tile::Door(link), _, _) => format!("D{}", link),
+            (Tile::Floor, None, None) => ".".to_string(),
+            (Tile::Floor, None, Some(spot)) => key(spot) + "S",
+            (Tile::Floor, Some(Object::Player), None) => "P".to_string(),
+            (Tile::Floor, Some(Object::Player), Some(spot)) => key(spot) + "+",
+            (Tile::Floor, Some(Object::Box(color)), Some(spot)) if color == spot => key(spot) + "*",
+            // A box can only start on a spot of its own color
+            (Tile::Floor, Some(Object::Box(color)), _) => key(color) + "B",
+        }
+    }
+}
+
+impl Display for Level {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for color in self.palette.colors.iter() {
+            let (r, g, b) = color.rgb;
+            writeln!(
+                fmt,
+                "; color {} {} {} {} {}",
+                color.key, color.name, r, g, b
+            )?;
+        }
+        if self.rule_mode == RuleMode::Classic {
+            writeln!(fmt, "; rules classic")?;
+        }
+        for y in 0..self.height {
+            let row: Vec<String> = (0..self.width)
+                .map(|x| self.token(self.cell(x, y)))
+                .collect();
+            writeln!(fmt, "{}", row.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+// Level currently loaded in the world
+#[derive(Default)]
+pub struct CurrentLevel {
+    pub level: Level,
+    pub errors: Vec<String>,
+}
+
+// Parse and validate a level before spawning it, nothing is spawned on errors
+pub fn load_map(world: &mut World, map_string: String) -> Result<(), Vec<MapError>> {
+    let level = Level::parse(&map_string)?;
+    let errors = level.validate();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let level = orient(world, level);
+    spawn_level(world, level);
+    Ok(())
+}
+
+pub fn spawn_level(world: &mut World, level: Level) {
+    let mut players = 0;
+    for y in 0..level.height {
+        for x in 0..level.width {
+            let cell = level.cell(x, y);
+            // Create the position at which to create something on the map
+            let position = Position {
+                x: x as u8,
+                y: y as u8,
+                z: 0, // we will get the z from the factory functions
+            };
+
+            if cell.tile == Tile::Nothing {
+                continue;
+            }
+            create_floor(world, position);
+            match cell.tile {
+                Tile::Wall => create_wall(world, position),
+                Tile::Ice => create_ice(world, position),
+                Tile::OneWay(direction) => create_one_way(world, position, direction),
+                Tile::Teleporter(pair) => create_teleporter(world, position, pair),
+                Tile::Hole => create_hole(world, position),
+                Tile::Plate(link) => create_plate(world, position, link),
+                Tile::Door(link) => create_door(world, position, link),
+                Tile::Nothing | Tile::Floor => (),
+            }
+            if let Some(color) = &cell.spot {
+                create_box_spot(world, position, color.clone());
+            }
+            match &cell.object {
+                Some(Object::Player) => {
+                    create_player(world, position, players);
+                    players += 1;
+                }
+                Some(Object::Box(color)) => create_box(world, position, color.clone()),
+                None => (),
+            }
+        }
+    }
+
+    world.write_resource::<Rules>().level_mode = level.rule_mode;
+    world.insert(level.palette.clone());
+    world.insert(CurrentLevel {
+        level,
+        errors: Vec::new(),
+    });
+    world.write_resource::<EventBus>().push(Event::LevelLoaded);
+}
+
+// Start the current level over from its initial layout
+pub fn restart_level(world: &mut World) {
+    let level = {
+        let current = world.read_resource::<CurrentLevel>();
+        if !current.errors.is_empty() {
+            return;
+        }
+        current.level.clone()
+    };
+    reset_world(world);
+    spawn_level(world, level);
+    world
+        .write_resource::<EventBus>()
+        .push(Event::LevelRestarted);
+}
+
+// Remove every entity and per level state before spawning another level
+pub fn reset_world(world: &mut World) {
+    world.delete_all();
+    world.maintain();
+    world.insert(InputQueue::default());
+    world.insert(MoveHistory::default());
+    world.insert(Gameplay::default());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Parsing errors, or the validation ones once the level parses
+    fn errors(map: &str) -> Vec<String> {
+        let errors = match Level::parse(map) {
+            Ok(level) => level.validate(),
+            Err(errors) => errors,
+        };
+        errors.iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn valid_level() {
+        let map = "W W W W W\nW P B S W\nW W W W W\n";
+        assert!(errors(map).is_empty());
+    }
+
+    #[test]
+    fn unrecognized_item() {
+        let map = "W W W W W\nW P B S W\nW W X é W\n";
+        assert_eq!(
+            errors(map),
+            vec![
+                "3:3: unrecognized map item X",
+                "3:4: unrecognized map item é"
+            ]
+        );
+    }
+
+    #[test]
+    fn unrecognized_color() {
+        let map = "W W W W W\nW P GB S W\nW W W W W\n";
+        assert_eq!(errors(map), vec!["2:3: unrecognized box color G"]);
+    }
+
+    #[test]
+    fn invalid_directive() {
+        let map = "; rules classic\n; color R red 300 0 0\nW W W W W\nW P B S W\nW W W W W\n";
+        assert_eq!(
+            errors(map),
+            vec!["2: invalid directive color R red 300 0 0"]
+        );
+    }
+
+    #[test]
+    fn too_large() {
+        let row = vec!["W"; MAX_SIZE + 1].join(" ");
+        let map = format!("W W W W W\nW P B S W\nW W W W W\n{}\n", row);
+        assert_eq!(
+            errors(&map),
+            vec!["4:257: level larger than 256 by 256 items"]
+        );
+    }
+
+    #[test]
+    fn player_count() {
+        let map = "W W W W W\nW . B S W\nW W W W W\n";
+        assert_eq!(errors(map), vec!["expected 1 to 4 players, found 0"]);
+        let map = "W W W W W W W W W\nW P P P P P B S W\nW W W W W W W W W\n";
+        assert_eq!(errors(map), vec!["expected 1 to 4 players, found 5"]);
+    }
+
+    #[test]
+    fn not_enclosed() {
+        let map = "W W W W W\nW P B S .\nW W W W W\n";
+        assert_eq!(errors(map), vec!["2:5: level is not enclosed by walls"]);
+    }
+
+    #[test]
+    fn box_count() {
+        let map = "W W W W W W\nW P B B S W\nW W W W W W\n";
+        assert_eq!(errors(map), vec!["2 boxes for 1 spots"]);
+        let map = "W W W\nW P W\nW W W\n";
+        assert_eq!(errors(map), vec!["0 boxes for 0 spots"]);
+        // a hole takes the extra box
+        let map = "W W W W W W W\nW P B B H S W\nW W W W W W W\n";
+        assert!(errors(map).is_empty());
+    }
+
+    #[test]
+    fn color_counts() {
+        let map = "W W W W W W\nW P RB RB BS W\nW . . . RS W\nW W W W W W\n";
+        assert_eq!(
+            errors(map),
+            vec!["too many red boxes", "not enough blue boxes"]
+        );
+        // neutral spots take any box
+        let map = "W W W W W W\nW P RB RB S W\nW . . . RS W\nW W W W W W\n";
+        assert!(errors(map).is_empty());
+    }
+
+    #[test]
+    fn unreachable_box_and_spot() {
+        let map = "W W W W W W\nW P B W S W\nW . S W B W\nW W W W W W\n";
+        assert_eq!(
+            errors(map),
+            vec![
+                "2:5: spot out of the player's reach",
+                "3:5: box out of the player's reach"
+            ]
+        );
+    }
+
+    #[test]
+    fn unpaired_teleporter() {
+        let map = "W W W W W W\nW P B S T1 W\nW W W W W W\n";
+        assert_eq!(errors(map), vec!["teleporter T1 must appear exactly twice"]);
+    }
+
+    #[test]
+    fn unmatched_link() {
+        let map = "W W W W W W\nW P B S _2 W\nW W W W W W\n";
+        assert_eq!(
+            errors(map),
+            vec!["link 2 needs both plates (_2) and doors (D2)"]
+        );
     }
 }
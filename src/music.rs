@@ -0,0 +1,146 @@
+use audio::SoundSource;
+use ggez::{audio, filesystem, timer, Context};
+use log::warn;
+use specs::{World, WorldExt};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::audio::AudioStore;
+use crate::editor::Editor;
+use crate::map::CurrentLevel;
+use crate::resources::{Gameplay, GameplayState};
+
+const MUSIC_DIR: &str = "/music";
+const EXTENSIONS: [&str; 4] = ["ogg", "wav", "mp3", "flac"];
+// seconds for a track to fade in or out
+const CROSSFADE: f32 = 1.5;
+// music volume while the victory jingle plays, and for how long
+const DUCK_VOLUME: f32 = 0.3;
+const DUCK_TIME: f32 = 3.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Mood {
+    Menu,
+    Level,
+}
+
+impl Mood {
+    fn dir(&self) -> &'static str {
+        match self {
+            Mood::Menu => "menu",
+            Mood::Level => "level",
+        }
+    }
+}
+
+struct Track {
+    source: audio::Source,
+    mood: Mood,
+    fade: f32,
+}
+
+// Background tracks of each mood are played in turn, looping over the playlist
+#[derive(Default)]
+pub struct Music {
+    pub playlists: HashMap<Mood, Vec<PathBuf>>,
+    next: HashMap<Mood, usize>,
+    current: Option<Track>,
+    fading_out: Vec<Track>,
+    ducked: f32,
+    won: bool,
+}
+
+impl Music {
+    fn start(&mut self, context: &mut Context, mood: Mood, fade: f32) -> Option<Track> {
+        loop {
+            let playlist = self.playlists.get_mut(&mood)?;
+            if playlist.is_empty() {
+                return None;
+            }
+            let index = self.next.get(&mood).copied().unwrap_or(0) % playlist.len();
+            let path = playlist[index].clone();
+            let started = audio::Source::new(context, &path).and_then(|mut source| {
+                source.set_volume(0.0);
+                source.play(context).map(|_| source)
+            });
+            match started {
+                Ok(source) => {
+                    self.next.insert(mood, index + 1);
+                    return Some(Track { source, mood, fade });
+                }
+                // Drop the track so it is not retried every frame
+                Err(e) => {
+                    warn!("Could not play {}: {}", path.display(), e);
+                    playlist.remove(index);
+                }
+            }
+        }
+    }
+}
+
+pub fn initialize_music(world: &mut World, context: &mut Context) {
+    let mut music = world.write_resource::<Music>();
+    for mood in [Mood::Menu, Mood::Level].iter() {
+        let dir = format!("{}/{}", MUSIC_DIR, mood.dir());
+        let mut tracks: Vec<PathBuf> = match filesystem::read_dir(context, &dir) {
+            Ok(paths) => paths
+                .filter(|p| {
+                    p.extension()
+                        .is_some_and(|e| EXTENSIONS.iter().any(|ext| e == *ext))
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        tracks.sort();
+        music.playlists.insert(*mood, tracks);
+    }
+}
+
+pub fn update_music(world: &mut World, context: &mut Context) {
+    let audio_store = world.read_resource::<AudioStore>();
+    if !audio_store.backend.has_device() {
+        return;
+    }
+    let mood = if world.read_resource::<Editor>().is_editing()
+        || !world.read_resource::<CurrentLevel>().errors.is_empty()
+    {
+        Mood::Menu
+    } else {
+        Mood::Level
+    };
+    let won = world.read_resource::<Gameplay>().state == GameplayState::Won;
+    let delta = timer::delta(context).as_secs_f32();
+    let mut music = world.write_resource::<Music>();
+
+    if won && !music.won {
+        music.ducked = DUCK_TIME;
+    }
+    music.won = won;
+    music.ducked = (music.ducked - delta).max(0.0);
+
+    // Crossfade when the mood changes, and go on with the playlist when a track ends
+    if music.current.as_ref().is_none_or(|t| t.mood != mood) {
+        if let Some(track) = music.current.take() {
+            music.fading_out.push(track);
+        }
+        music.current = music.start(context, mood, 0.0);
+    } else if music.current.as_ref().is_some_and(|t| t.source.stopped()) {
+        music.current = music.start(context, mood, 1.0);
+    }
+
+    let step = delta / CROSSFADE;
+    let duck = if music.ducked > 0.0 { DUCK_VOLUME } else { 1.0 };
+    let gain = audio_store.settings.music_gain() * duck;
+    if let Some(track) = music.current.as_mut() {
+        track.fade = (track.fade + step).min(1.0);
+        track.source.set_volume(gain * track.fade);
+    }
+    for track in music.fading_out.iter_mut() {
+        track.fade -= step;
+        track.source.set_volume(gain * track.fade.max(0.0));
+        if track.fade <= 0.0 {
+            let _ = track.source.stop(context);
+        }
+    }
+    music.fading_out.retain(|t| t.fade > 0.0);
+}
@@ -0,0 +1,250 @@
+use std::collections::BTreeMap;
+
+use crate::components::BoxColor;
+use crate::map::{Cell, Level, Object, Tile};
+use crate::resources::{Palette, PaletteColor, RuleMode};
+use crate::solver::Board;
+use crate::transform::Transform;
+
+// Drop what the players can never reach and the walls not bordering it, then
+// crop the level to what is left
+pub fn trim(level: &Level) -> Level {
+    let players = level.players();
+    if players.is_empty() {
+        return level.clone();
+    }
+    let mut reachable = vec![false; level.width * level.height];
+    for player in players {
+        for (cell, reached) in reachable.iter_mut().zip(level.reachable(player).0) {
+            *cell |= reached;
+        }
+    }
+    let reached = |x: i64, y: i64| {
+        x >= 0
+            && y >= 0
+            && (x as usize) < level.width
+            && (y as usize) < level.height
+            && reachable[y as usize * level.width + x as usize]
+    };
+
+    let mut kept = level.clone();
+    for y in 0..level.height {
+        for x in 0..level.width {
+            let borders_reachable = (-1..=1)
+                .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                .any(|(dx, dy)| reached(x as i64 + dx, y as i64 + dy));
+            let keep = match level.cell(x, y).tile {
+                Tile::Wall => borders_reachable,
+                _ => reachable[y * level.width + x],
+            };
+            if !keep {
+                *kept.cell_mut(x, y) = Cell::new(Tile::Nothing);
+            }
+        }
+    }
+
+    let used: Vec<(usize, usize)> = (0..level.height)
+        .flat_map(|y| (0..level.width).map(move |x| (x, y)))
+        .filter(|(x, y)| kept.cell(*x, *y).tile != Tile::Nothing)
+        .collect();
+    let min_x = used.iter().map(|p| p.0).min().unwrap_or(0);
+    let max_x = used.iter().map(|p| p.0).max().unwrap_or(0);
+    let min_y = used.iter().map(|p| p.1).min().unwrap_or(0);
+    let max_y = used.iter().map(|p| p.1).max().unwrap_or(0);
+
+    let mut trimmed = Level::new(max_x - min_x + 1, max_y - min_y + 1, level.palette.clone());
+    trimmed.rule_mode = level.rule_mode;
+    for y in min_y..=max_y {
+        for x in min_x..=max_x {
+            *trimmed.cell_mut(x - min_x, y - min_y) = kept.cell(x, y).clone();
+        }
+    }
+    trimmed
+}
+
+// Move the player to the first cell of its area, as any cell of it is equivalent
+fn normalize_player(level: &mut Level) {
+    // where players stand relative to each other matters, and the area is only
+    // known on plain levels
+    if level.players().len() != 1 || level.has_special_tiles() {
+        return;
+    }
+    let (board, state) = Board::new(level);
+    let state = match state {
+        Some(state) => state,
+        None => return,
+    };
+    let reachable = board.reachable(&state);
+    if let Some(cell) = reachable.iter().position(|r| *r) {
+        let from = level.cell_mut(state.player % level.width, state.player / level.width);
+        from.object = None;
+        level
+            .cell_mut(cell % level.width, cell / level.width)
+            .object = Some(Object::Player);
+    }
+}
+
+// Text identifying a level layout, leaving out the palette: colors are keyed by
+// their first use, so that levels only differing by their keys match
+fn layout(level: &Level) -> String {
+    let mut names: Vec<&String> = Vec::new();
+    for y in 0..level.height {
+        for x in 0..level.width {
+            let cell = level.cell(x, y);
+            let object_color = match &cell.object {
+                Some(Object::Box(color)) => Some(color),
+                _ => None,
+            };
+            for color in cell.spot.iter().chain(object_color) {
+                if let BoxColor::Colored(name) = color {
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+            }
+        }
+    }
+    let mut keyed = level.clone();
+    keyed.palette = Palette {
+        colors: names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| PaletteColor {
+                key: index.to_string(),
+                name: name.to_string(),
+                rgb: (0, 0, 0),
+            })
+            .collect(),
+    };
+
+    let text = keyed.to_string();
+    let rows: Vec<&str> = text.lines().filter(|l| !l.starts_with(';')).collect();
+    let rules = match level.rule_mode {
+        RuleMode::Classic => "classic",
+        RuleMode::Colored => "colored",
+    };
+    format!("{}\n{}", rules, rows.join("\n"))
+}
+
+// Trimmed level in the orientation giving the smallest layout
+pub fn canonical(level: &Level) -> Level {
+    let trimmed = trim(level);
+    Transform::all()
+        .iter()
+        .map(|transform| {
+            let mut transformed = transform.apply(&trimmed);
+            normalize_player(&mut transformed);
+            (layout(&transformed), transformed)
+        })
+        .min_by(|a, b| a.0.cmp(&b.0))
+        .map(|(_, level)| level)
+        .unwrap_or(trimmed)
+}
+
+// FNV-1a hash of the canonical layout, stable across runs and platforms
+pub fn level_hash(level: &Level) -> u64 {
+    layout(&canonical(level))
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+// Print the hash of each level file, and the files holding the same level.
+// Levels with special tiles are skipped, as copies where the player starts
+// elsewhere in its area can't be told apart from other levels.
+pub fn run_cli(paths: &[String]) -> Result<(), String> {
+    // sorted by hash, for the duplicates to print in a stable order
+    let mut by_hash: BTreeMap<u64, Vec<&String>> = BTreeMap::new();
+    for path in paths {
+        let content = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let level = Level::parse(&content).map_err(|errors| {
+            let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            format!("{}: {}", path, errors.join(", "))
+        })?;
+        if level.has_special_tiles() {
+            println!("{:16} {} (special tiles)", "skipped", path);
+            continue;
+        }
+        let hash = level_hash(&level);
+        println!("{:016x} {}", hash, path);
+        by_hash.entry(hash).or_default().push(path);
+    }
+
+    for (hash, paths) in by_hash.iter().filter(|(_, paths)| paths.len() > 1) {
+        let paths: Vec<&str> = paths.iter().map(|p| p.as_str()).collect();
+        println!("duplicates {:016x}: {}", hash, paths.join(", "));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEVEL: &str = "
+    W W W W W W
+    W P . . . W
+    W . B W S W
+    W . . . . W
+    W W W W W W
+    ";
+
+    fn hash(map: &str) -> u64 {
+        level_hash(&Level::parse(map).expect("expected a valid level"))
+    }
+
+    #[test]
+    fn transformed_copies_match() {
+        let level = Level::parse(LEVEL).expect("expected a valid level");
+        for transform in Transform::all() {
+            assert_eq!(level_hash(&transform.apply(&level)), level_hash(&level));
+        }
+        let canonical_level = canonical(&level);
+        assert_eq!(
+            canonical(&canonical_level).to_string(),
+            canonical_level.to_string()
+        );
+    }
+
+    #[test]
+    fn player_start_and_unreachable_parts_are_ignored() {
+        let moved = "
+        W W W W W W
+        W . . . . W
+        W . B W S W
+        W . . . P W
+        W W W W W W
+        ";
+        assert_eq!(hash(moved), hash(LEVEL));
+        let with_pocket = "
+        W W W W W W W W W
+        W P . . . W . . W
+        W . B W S W . . W
+        W . . . . W W W W
+        W W W W W W N N N
+        ";
+        assert_eq!(hash(with_pocket), hash(LEVEL));
+    }
+
+    #[test]
+    fn different_levels_differ() {
+        let moved_spot = "
+        W W W W W W
+        W P . . S W
+        W . B W . W
+        W . . . . W
+        W W W W W W
+        ";
+        assert_ne!(hash(moved_spot), hash(LEVEL));
+        let classic = format!("; rules classic\n{}", LEVEL);
+        assert_ne!(hash(&classic), hash(LEVEL));
+    }
+
+    #[test]
+    fn players_are_kept_on_special_tile_levels() {
+        let left = "W W W W W W W\nW P I . B S W\nW W W W W W W\n";
+        let right = "W W W W W W W\nW . I P B S W\nW W W W W W W\n";
+        assert_ne!(hash(left), hash(right));
+    }
+}
@@ -1,27 +1,33 @@
 use ggez::event::KeyCode;
-use specs::World;
+use specs::{world::Index, Entity, World};
+use std::collections::{HashMap, VecDeque};
 use std::fmt;
+use std::sync::Mutex;
 use std::{fmt::Display, time::Duration};
 
 use crate::audio::AudioStore;
-use crate::events::Event;
+use crate::components::{BoxColor, Position};
+use crate::daily::Daily;
+use crate::editor::Editor;
+use crate::events::EventBus;
+use crate::generator::RandomLevels;
+use crate::map::CurrentLevel;
+use crate::music::Music;
+use crate::theme::Themes;
+use crate::transform::Orientation;
 // Resource
 #[derive(Default)]
 pub struct InputQueue {
     pub keys_pressed: Vec<KeyCode>,
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub enum GameplayState {
+    #[default]
     Playing,
     Won,
 }
 
-impl Default for GameplayState {
-    fn default() -> Self {
-        Self::Playing
-    }
-}
-
 impl Display for GameplayState {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         fmt.write_str(match self {
@@ -36,6 +42,7 @@ impl Display for GameplayState {
 pub struct Gameplay {
     pub state: GameplayState,
     pub moves_count: u32,
+    pub pushes_count: u32,
 }
 
 #[derive(Default)]
@@ -43,9 +50,162 @@ pub struct Time {
     pub delta: Duration,
 }
 
+// What the F3 overlay shows on top of the rendered world
 #[derive(Default)]
-pub struct EventQueue {
-    pub events: Vec<Event>,
+pub struct DebugInfo {
+    pub visible: bool,
+    // behind a lock so systems running in parallel can record their timing
+    pub timings: Mutex<Vec<(&'static str, Duration)>>,
+    pub events: VecDeque<String>,
+}
+
+impl DebugInfo {
+    pub fn record(&self, system: &'static str, duration: Duration) {
+        if let Ok(mut timings) = self.timings.lock() {
+            match timings.iter_mut().find(|(name, _)| *name == system) {
+                Some(timing) => timing.1 = duration,
+                None => timings.push((system, duration)),
+            }
+        }
+    }
+}
+
+// Entities on each tile, kept up to date by the SpatialIndexSystem and the
+// MovementSystem
+#[derive(Default)]
+pub struct SpatialIndex {
+    cells: HashMap<(u8, u8), Vec<Entity>>,
+    positions: HashMap<Index, (u8, u8)>,
+}
+
+impl SpatialIndex {
+    pub fn at(&self, x: u8, y: u8) -> &[Entity] {
+        self.cells
+            .get(&(x, y))
+            .map_or(&[], |entities| entities.as_slice())
+    }
+
+    pub fn insert(&mut self, entity: Entity, x: u8, y: u8) {
+        self.remove(entity.id());
+        self.cells.entry((x, y)).or_default().push(entity);
+        self.positions.insert(entity.id(), (x, y));
+    }
+
+    pub fn remove(&mut self, id: Index) {
+        if let Some(tile) = self.positions.remove(&id) {
+            if let Some(entities) = self.cells.get_mut(&tile) {
+                entities.retain(|entity| entity.id() != id);
+            }
+        }
+    }
+}
+
+// Positions of the entities before each move, most recent last
+#[derive(Default)]
+pub struct MoveHistory {
+    pub moves: Vec<Vec<(Index, Position)>>,
+}
+
+#[derive(Clone)]
+pub struct PaletteColor {
+    pub key: String,
+    pub name: String,
+    pub rgb: (u8, u8, u8),
+}
+
+// Box colors available in the current level, referenced by key in the map tokens
+#[derive(Clone)]
+pub struct Palette {
+    pub colors: Vec<PaletteColor>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        let color = |key: &str, name: &str, rgb| PaletteColor {
+            key: key.to_string(),
+            name: name.to_string(),
+            rgb,
+        };
+        Self {
+            colors: vec![
+                color("R", "red", (200, 60, 60)),
+                color("B", "blue", (60, 90, 200)),
+            ],
+        }
+    }
+}
+
+impl Palette {
+    pub fn color(&self, key: &str) -> Option<BoxColor> {
+        self.colors
+            .iter()
+            .find(|c| c.key == key)
+            .map(|c| BoxColor::Colored(c.name.clone()))
+    }
+
+    pub fn key(&self, color: &BoxColor) -> Option<&str> {
+        match color {
+            BoxColor::Neutral => Some(""),
+            BoxColor::Colored(name) => self
+                .colors
+                .iter()
+                .find(|c| &c.name == name)
+                .map(|c| c.key.as_str()),
+        }
+    }
+
+    pub fn rgb(&self, color: &BoxColor) -> Option<(u8, u8, u8)> {
+        match color {
+            BoxColor::Neutral => None,
+            BoxColor::Colored(name) => self.colors.iter().find(|c| &c.name == name).map(|c| c.rgb),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RuleMode {
+    // Boxes must be placed on spots of their own color
+    Colored,
+    // Any box on any spot counts, as in standard Sokoban
+    Classic,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MoveRule {
+    // Boxes are pushed ahead of the player
+    Push,
+    // Boxes behind the player follow it, as in reverse Sokoban
+    Pull,
+}
+
+// Rule mode declared by the level, unless forced globally
+pub struct Rules {
+    pub level_mode: RuleMode,
+    pub global_mode: Option<RuleMode>,
+    // Most boxes pushed at once, 1 in classic Sokoban, no limit when None
+    pub max_push_chain: Option<usize>,
+    pub move_rule: MoveRule,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            level_mode: RuleMode::Colored,
+            global_mode: None,
+            max_push_chain: None,
+            move_rule: MoveRule::Push,
+        }
+    }
+}
+
+impl Rules {
+    pub fn mode(&self) -> RuleMode {
+        self.global_mode.unwrap_or(self.level_mode)
+    }
+
+    pub fn fits(&self, the_box: &BoxColor, spot: &BoxColor) -> bool {
+        self.mode() == RuleMode::Classic || the_box.fits(spot)
+    }
 }
 
 // Registering resources
@@ -53,6 +213,18 @@ pub fn register_resources(world: &mut World) {
     world.insert(InputQueue::default());
     world.insert(Gameplay::default());
     world.insert(Time::default());
-    world.insert(EventQueue::default());
+    world.insert(EventBus::default());
+    world.insert(MoveHistory::default());
     world.insert(AudioStore::default());
+    world.insert(Themes::default());
+    world.insert(Palette::default());
+    world.insert(Rules::default());
+    world.insert(CurrentLevel::default());
+    world.insert(Editor::default());
+    world.insert(RandomLevels::default());
+    world.insert(Daily::default());
+    world.insert(Orientation::default());
+    world.insert(Music::default());
+    world.insert(DebugInfo::default());
+    world.insert(SpatialIndex::default());
 }
@@ -0,0 +1,379 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+
+use crate::components::BoxColor;
+use crate::map::{Level, Object, Tile};
+use crate::resources::RuleMode;
+
+// Directions in LURD order, with the letter used to record a move
+pub const DIRECTIONS: [(i32, i32, char); 4] =
+    [(-1, 0, 'l'), (0, -1, 'u'), (1, 0, 'r'), (0, 1, 'd')];
+
+pub const MAX_NODES: usize = 200_000;
+
+pub struct Solution {
+    // LURD notation, uppercase letters being pushes
+    pub moves: String,
+    pub pushes: usize,
+}
+
+pub struct Search {
+    pub solution: Option<Solution>,
+    pub nodes: usize,
+}
+
+// Static part of a level, colors being indexed with 0 as neutral
+pub struct Board {
+    pub width: usize,
+    pub height: usize,
+    pub walls: Vec<bool>,
+    pub spots: Vec<Option<u8>>,
+    pub classic: bool,
+}
+
+#[derive(Clone)]
+pub struct State {
+    // box cells and colors, sorted by cell
+    pub boxes: Vec<(usize, u8)>,
+    pub player: usize,
+    // one bit per cell holding a box, so pushes don't look through the boxes
+    occupied: Vec<u64>,
+}
+
+// The occupied cells follow from the boxes, only those and the player tell states
+// apart
+impl PartialEq for State {
+    fn eq(&self, other: &Self) -> bool {
+        self.boxes == other.boxes && self.player == other.player
+    }
+}
+
+impl Eq for State {}
+
+impl Hash for State {
+    fn hash<H: Hasher>(&self, hasher: &mut H) {
+        self.boxes.hash(hasher);
+        self.player.hash(hasher);
+    }
+}
+
+impl State {
+    pub fn new(mut boxes: Vec<(usize, u8)>, player: usize, cells: usize) -> Self {
+        let mut occupied = vec![0; cells.div_ceil(64)];
+        for (cell, _) in boxes.iter() {
+            occupied[cell / 64] |= 1 << (cell % 64);
+        }
+        boxes.sort_unstable();
+        Self {
+            boxes,
+            player,
+            occupied,
+        }
+    }
+
+    pub fn has_box(&self, cell: usize) -> bool {
+        self.occupied
+            .get(cell / 64)
+            .is_some_and(|bits| bits & (1 << (cell % 64)) != 0)
+    }
+
+    // Move the box standing on a cell to a free one
+    pub fn move_box(&mut self, from: usize, to: usize) {
+        if !self.has_box(from) {
+            return;
+        }
+        self.occupied[from / 64] &= !(1 << (from % 64));
+        self.occupied[to / 64] |= 1 << (to % 64);
+        for the_box in self.boxes.iter_mut().filter(|b| b.0 == from) {
+            the_box.0 = to;
+        }
+        self.boxes.sort_unstable();
+    }
+}
+
+impl Board {
+    pub fn new(level: &Level) -> (Self, Option<State>) {
+        let color_index = |color: &BoxColor| match color {
+            BoxColor::Neutral => 0,
+            BoxColor::Colored(name) => level
+                .palette
+                .colors
+                .iter()
+                .position(|c| &c.name == name)
+                .map_or(0, |i| i as u8 + 1),
+        };
+
+        let mut walls = Vec::new();
+        let mut spots = Vec::new();
+        let mut boxes = Vec::new();
+        let mut player = None;
+        for y in 0..level.height {
+            for x in 0..level.width {
+                let cell = level.cell(x, y);
+                let index = y * level.width + x;
+                // Only the first player moves, any other one standing still like a
+                // wall, so the solutions found also hold for several players. Ice,
+                // one-way, teleporter, hole, plate and door tiles are walls too, being
+                // avoided.
+                let other_player = cell.object == Some(Object::Player) && player.is_some();
+                walls.push(cell.tile != Tile::Floor || other_player);
+                spots.push(cell.spot.as_ref().map(color_index));
+                match &cell.object {
+                    Some(Object::Player) if other_player => (),
+                    Some(Object::Player) => player = Some(index),
+                    Some(Object::Box(color)) => boxes.push((index, color_index(color))),
+                    None => (),
+                }
+            }
+        }
+
+        let board = Self {
+            width: level.width,
+            height: level.height,
+            walls,
+            spots,
+            classic: level.rule_mode == RuleMode::Classic,
+        };
+        let cells = level.width * level.height;
+        let state = player.map(|player| State::new(boxes, player, cells));
+        (board, state)
+    }
+
+    pub fn step(&self, cell: usize, (dx, dy, _): (i32, i32, char)) -> Option<usize> {
+        let x = (cell % self.width) as i32 + dx;
+        let y = (cell / self.width) as i32 + dy;
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            None
+        } else {
+            Some(y as usize * self.width + x as usize)
+        }
+    }
+
+    fn fits(&self, the_box: u8, spot: u8) -> bool {
+        self.classic || the_box == 0 || spot == 0 || the_box == spot
+    }
+
+    // Cells the player can walk to without pushing anything
+    pub fn reachable(&self, state: &State) -> Vec<bool> {
+        let mut visited = vec![false; self.walls.len()];
+        let mut stack = vec![state.player];
+        visited[state.player] = true;
+        while let Some(cell) = stack.pop() {
+            // neighbors worked out directly, this being the hot loop of the search
+            let (x, y) = (cell % self.width, cell / self.width);
+            let neighbors = [
+                (x > 0, cell.wrapping_sub(1)),
+                (y > 0, cell.wrapping_sub(self.width)),
+                (x + 1 < self.width, cell + 1),
+                (y + 1 < self.height, cell + self.width),
+            ];
+            for (inside, next) in neighbors.iter().copied() {
+                if inside && !visited[next] && !self.walls[next] && !state.has_box(next) {
+                    visited[next] = true;
+                    stack.push(next);
+                }
+            }
+        }
+        visited
+    }
+
+    pub fn is_solved(&self, state: &State) -> bool {
+        let spots_count = self.spots.iter().filter(|s| s.is_some()).count();
+        let placed = state
+            .boxes
+            .iter()
+            .filter(|(cell, color)| self.spots[*cell].is_some_and(|spot| self.fits(*color, spot)))
+            .count();
+        placed == spots_count.min(state.boxes.len())
+    }
+
+    // Cells from which a box can still be pushed to some spot
+    pub fn live_cells(&self) -> Vec<bool> {
+        let mut live = vec![false; self.walls.len()];
+        let mut stack: Vec<usize> = (0..self.walls.len())
+            .filter(|cell| self.spots[*cell].is_some())
+            .collect();
+        for cell in stack.iter() {
+            live[*cell] = true;
+        }
+        // Pull boxes away from the spots: the pusher needs room behind the box
+        while let Some(cell) = stack.pop() {
+            for direction in DIRECTIONS.iter() {
+                let (dx, dy, c) = *direction;
+                let previous = self.step(cell, (-dx, -dy, c));
+                let behind = previous.and_then(|p| self.step(p, (-dx, -dy, c)));
+                if let (Some(previous), Some(behind)) = (previous, behind) {
+                    if !live[previous] && !self.walls[previous] && !self.walls[behind] {
+                        live[previous] = true;
+                        stack.push(previous);
+                    }
+                }
+            }
+        }
+        live
+    }
+
+    // Push every contiguous box in front of the player, if nothing blocks them
+    pub fn push(&self, state: &State, from: usize, direction: (i32, i32, char)) -> Option<State> {
+        let first = self.step(from, direction)?;
+        let mut chain = Vec::new();
+        let mut cell = first;
+        while state.has_box(cell) {
+            chain.push(cell);
+            cell = self.step(cell, direction)?;
+        }
+        if chain.is_empty() || self.walls[cell] {
+            return None;
+        }
+
+        // the foremost box first, into the free cell ahead of the chain
+        let mut next = State {
+            player: first,
+            ..state.clone()
+        };
+        for the_box in chain.iter().rev() {
+            next.move_box(*the_box, self.step(*the_box, direction)?);
+        }
+        Some(next)
+    }
+
+    // Shortest walk between two cells, as lowercase LURD moves
+    pub fn walk(&self, state: &State, to: usize) -> Option<String> {
+        let mut previous: HashMap<usize, (usize, char)> = HashMap::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(state.player);
+        previous.insert(state.player, (state.player, ' '));
+
+        while let Some(cell) = queue.pop_front() {
+            if cell == to {
+                let mut moves = Vec::new();
+                let mut cell = cell;
+                while cell != state.player {
+                    let (from, c) = previous[&cell];
+                    moves.push(c);
+                    cell = from;
+                }
+                return Some(moves.iter().rev().collect());
+            }
+            for direction in DIRECTIONS.iter() {
+                if let Some(next) = self.step(cell, *direction) {
+                    if !self.walls[next] && !state.has_box(next) && !previous.contains_key(&next) {
+                        previous.insert(next, (cell, direction.2));
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+fn normalize(board: &Board, mut state: State) -> State {
+    let reachable = board.reachable(&state);
+    state.player = reachable.iter().position(|r| *r).unwrap_or(state.player);
+    state
+}
+
+// Breadth first search over push states, giving a solution with the fewest pushes
+pub fn solve(level: &Level, max_nodes: usize) -> Search {
+    let (board, start) = Board::new(level);
+    let start = match start {
+        Some(start) => start,
+        None => {
+            return Search {
+                solution: None,
+                nodes: 0,
+            }
+        }
+    };
+    let spots_count = board.spots.iter().filter(|s| s.is_some()).count();
+    // Extra boxes may rest anywhere, so dead cells only matter without them
+    let live = board.live_cells();
+    let prune = start.boxes.len() <= spots_count;
+
+    // every state with the state it comes from and the push leading to it
+    let mut states = vec![(normalize(&board, start.clone()), None)];
+    let mut seen: HashMap<State, usize> = HashMap::new();
+    seen.insert(states[0].0.clone(), 0);
+    let mut queue = VecDeque::new();
+    queue.push_back(0);
+    let mut nodes = 0;
+
+    while let Some(index) = queue.pop_front() {
+        if board.is_solved(&states[index].0) {
+            return Search {
+                solution: Some(replay(&board, &start, &states, index)),
+                nodes,
+            };
+        }
+        if nodes >= max_nodes {
+            break;
+        }
+        nodes += 1;
+
+        let state = states[index].0.clone();
+        let reachable = board.reachable(&state);
+        // pushes start next to a box, from a cell the player can reach
+        for (the_box, _) in state.boxes.iter() {
+            for direction in DIRECTIONS.iter() {
+                let (dx, dy, c) = *direction;
+                let cell = match board.step(*the_box, (-dx, -dy, c)) {
+                    Some(cell) if reachable[cell] => cell,
+                    _ => continue,
+                };
+                let next = match board.push(&state, cell, *direction) {
+                    Some(next) => next,
+                    None => continue,
+                };
+                if prune && next.boxes.iter().any(|(cell, _)| !live[*cell]) {
+                    continue;
+                }
+                let next = normalize(&board, next);
+                if !seen.contains_key(&next) {
+                    seen.insert(next.clone(), states.len());
+                    queue.push_back(states.len());
+                    states.push((next, Some((index, cell, *direction))));
+                }
+            }
+        }
+    }
+
+    Search {
+        solution: None,
+        nodes,
+    }
+}
+
+type Step = Option<(usize, usize, (i32, i32, char))>;
+
+// Rebuild the moves leading to a state, walking between each push
+fn replay(board: &Board, start: &State, states: &[(State, Step)], index: usize) -> Solution {
+    let mut pushes = Vec::new();
+    let mut current = index;
+    while let Some((parent, cell, direction)) = states[current].1 {
+        pushes.push((cell, direction));
+        current = parent;
+    }
+    pushes.reverse();
+
+    let mut state = start.clone();
+    let mut moves = String::new();
+    for (cell, direction) in pushes.iter() {
+        moves += &board.walk(&state, *cell).unwrap_or_default();
+        moves.push(direction.2.to_ascii_uppercase());
+        state = board
+            .push(
+                &State {
+                    player: *cell,
+                    ..state
+                },
+                *cell,
+                *direction,
+            )
+            .expect("expected a valid push");
+    }
+    Solution {
+        moves,
+        pushes: pushes.len(),
+    }
+}
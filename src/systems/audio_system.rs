@@ -0,0 +1,33 @@
+use specs::shrev::ReaderId;
+use specs::{Read, System, SystemData, World, Write};
+
+use crate::{
+    audio::AudioStore,
+    events::{EventBus, TimedEvent},
+};
+
+// Plays the sound configured for each event
+#[derive(Default)]
+pub struct AudioSystem {
+    reader: Option<ReaderId<TimedEvent>>,
+}
+
+impl<'a> System<'a> for AudioSystem {
+    type SystemData = (Read<'a, EventBus>, Write<'a, AudioStore>);
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.reader = Some(world.fetch_mut::<EventBus>().register_reader());
+    }
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (event_bus, mut audio_store) = data;
+        let reader = self
+            .reader
+            .as_mut()
+            .expect("expected setup to register a reader");
+        for timed_event in event_bus.read(reader) {
+            audio_store.play_sound(timed_event.event.sound_name(), timed_event.time);
+        }
+    }
+}
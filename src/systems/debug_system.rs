@@ -0,0 +1,44 @@
+use log::debug;
+use specs::shrev::ReaderId;
+use specs::{Read, System, SystemData, World, Write};
+
+use crate::{
+    events::{EventBus, TimedEvent},
+    resources::DebugInfo,
+};
+
+const RECENT_EVENTS: usize = 6;
+
+// Logs every event and keeps the latest ones for the debug overlay
+#[derive(Default)]
+pub struct DebugSystem {
+    reader: Option<ReaderId<TimedEvent>>,
+}
+
+impl<'a> System<'a> for DebugSystem {
+    type SystemData = (Read<'a, EventBus>, Write<'a, DebugInfo>);
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.reader = Some(world.fetch_mut::<EventBus>().register_reader());
+    }
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (event_bus, mut debug_info) = data;
+        let reader = self
+            .reader
+            .as_mut()
+            .expect("expected setup to register a reader");
+        for timed_event in event_bus.read(reader) {
+            debug!("{:?}", timed_event);
+            let line = format!(
+                "{}.{} {:?}",
+                timed_event.frame, timed_event.sequence, timed_event.event
+            );
+            debug_info.events.push_back(line);
+            if debug_info.events.len() > RECENT_EVENTS {
+                debug_info.events.pop_front();
+            }
+        }
+    }
+}
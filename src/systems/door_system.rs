@@ -0,0 +1,94 @@
+use specs::shrev::ReaderId;
+use specs::{
+    join::Join, Entities, Read, ReadStorage, System, SystemData, World, Write, WriteStorage,
+};
+use std::collections::HashSet;
+
+use crate::{
+    components::{Door, Immovable, Movable, Plate, Position, Renderable, Sprite},
+    events::{Event, EventBus, TimedEvent},
+    resources::SpatialIndex,
+};
+
+// Opens the doors whose link has a pressed plate, and closes the other ones
+// once nothing stands in the doorway
+#[derive(Default)]
+pub struct DoorSystem {
+    reader: Option<ReaderId<TimedEvent>>,
+}
+
+impl<'a> System<'a> for DoorSystem {
+    type SystemData = (
+        Write<'a, EventBus>,
+        Read<'a, SpatialIndex>,
+        Entities<'a>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Movable>,
+        ReadStorage<'a, Plate>,
+        WriteStorage<'a, Door>,
+        WriteStorage<'a, Immovable>,
+        WriteStorage<'a, Renderable>,
+    );
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.reader = Some(world.fetch_mut::<EventBus>().register_reader());
+    }
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            mut event_bus,
+            spatial_index,
+            entities,
+            positions,
+            movables,
+            plates,
+            mut doors,
+            mut immovables,
+            mut renderables,
+        ) = data;
+        let reader = self
+            .reader
+            .as_mut()
+            .expect("expected setup to register a reader");
+
+        let board_changed = event_bus
+            .read(reader)
+            .any(|timed_event| timed_event.event.changes_board());
+        if !board_changed {
+            return;
+        }
+
+        let occupied = |position: &Position| {
+            spatial_index
+                .at(position.x, position.y)
+                .iter()
+                .any(|e| movables.contains(*e))
+        };
+        let pressed: HashSet<u8> = (&plates, &positions)
+            .join()
+            .filter(|(_, position)| occupied(position))
+            .map(|(plate, _)| plate.link)
+            .collect();
+
+        for (entity, door, position, renderable) in
+            (&entities, &mut doors, &positions, &mut renderables).join()
+        {
+            let open = pressed.contains(&door.link) || (door.open && occupied(position));
+            if open == door.open {
+                continue;
+            }
+            door.open = open;
+            renderable.sprite = Sprite::Door { open };
+            if open {
+                immovables.remove(entity);
+                event_bus.push(Event::DoorOpened);
+            } else {
+                immovables
+                    .insert(entity, Immovable)
+                    .expect("expected a living door");
+                event_bus.push(Event::DoorClosed);
+            }
+        }
+    }
+}
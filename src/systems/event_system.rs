@@ -1,71 +1,63 @@
-use ggez::Context;
-use specs::{Entities, Join, ReadStorage, System, Write};
-use std::collections::HashMap;
+use specs::shrev::ReaderId;
+use specs::{Entities, Read, ReadStorage, System, SystemData, World, Write};
 
 use crate::{
-    audio::AudioStore,
     components::*,
-    events::{BoxPlacedOnSpot, EntityMoved, Event},
-    resources::EventQueue,
+    events::{BoxPlacedOnSpot, EntityMoved, Event, EventBus, TimedEvent},
+    resources::{Rules, SpatialIndex},
 };
 
-pub struct EventSystem<'a> {
-    pub context: &'a mut Context,
+// Turns box moves into box placement events
+#[derive(Default)]
+pub struct EventSystem {
+    reader: Option<ReaderId<TimedEvent>>,
 }
 
-impl<'a> System<'a> for EventSystem<'_> {
+impl<'a> System<'a> for EventSystem {
     type SystemData = (
-        Write<'a, EventQueue>,
-        Write<'a, AudioStore>,
+        Write<'a, EventBus>,
+        Read<'a, Rules>,
+        Read<'a, SpatialIndex>,
         Entities<'a>,
         ReadStorage<'a, Box>,
         ReadStorage<'a, BoxSpot>,
         ReadStorage<'a, Position>,
     );
 
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.reader = Some(world.fetch_mut::<EventBus>().register_reader());
+    }
+
     fn run(&mut self, data: Self::SystemData) {
-        let (mut event_queue, mut audio_store, entities, boxes, box_spots, positions) = data;
+        let (mut event_bus, rules, spatial_index, entities, boxes, box_spots, positions) = data;
+        let reader = self
+            .reader
+            .as_mut()
+            .expect("expected setup to register a reader");
         let mut new_events = Vec::new();
 
-        for event in event_queue.events.drain(..) {
-            println!("New event: {:?}", event);
-            match event {
-                Event::PlayerHitObstacle => {
-                    audio_store.play_sound(&"error".to_string(), self.context);
-                }
-
-                Event::EntityMoved(EntityMoved { id }) => {
-                    if let Some(the_box) = boxes.get(entities.entity(id)) {
-                        let box_spots_with_positions: HashMap<(u8, u8), &BoxSpot> =
-                            (&box_spots, &positions)
-                                .join()
-                                .map(|t| ((t.1.x, t.1.y), t.0))
-                                .collect::<HashMap<_, _>>();
-
-                        if let Some(box_position) = positions.get(entities.entity(id)) {
-                            // Check if there is a spot on this position, and if there
-                            // is if it's the correct or incorrect type
-                            if let Some(box_spot) =
-                                box_spots_with_positions.get(&(box_position.x, box_position.y))
-                            {
-                                new_events.push(Event::BoxPlacedOnSpot(BoxPlacedOnSpot {
-                                    is_correct_spot: (box_spot.color == the_box.color),
-                                }));
-                            }
+        for timed_event in event_bus.read(reader) {
+            if let Event::EntityMoved(EntityMoved { id }) = timed_event.event {
+                if let Some(the_box) = boxes.get(entities.entity(id)) {
+                    if let Some(box_position) = positions.get(entities.entity(id)) {
+                        // Check if there is a spot on this position, and if there
+                        // is if it's the correct or incorrect type
+                        let box_spot = spatial_index
+                            .at(box_position.x, box_position.y)
+                            .iter()
+                            .find_map(|e| box_spots.get(*e));
+                        if let Some(box_spot) = box_spot {
+                            new_events.push(Event::BoxPlacedOnSpot(BoxPlacedOnSpot {
+                                is_correct_spot: rules.fits(&the_box.color, &box_spot.color),
+                            }));
                         }
                     }
                 }
-                Event::BoxPlacedOnSpot(BoxPlacedOnSpot { is_correct_spot }) => {
-                    // play sound here
-                    let sound = if is_correct_spot {
-                        "correct"
-                    } else {
-                        "incorrect"
-                    };
-                    audio_store.play_sound(&sound.to_string(), self.context);
-                }
             }
         }
-        event_queue.events.append(&mut new_events);
+        for event in new_events {
+            event_bus.push(event);
+        }
     }
 }
@@ -1,41 +1,170 @@
-use specs::{Join, ReadStorage, System, Write};
-use std::collections::HashMap;
+use specs::shrev::ReaderId;
+use specs::{Join, Read, ReadStorage, System, SystemData, World, Write};
 
 use crate::{
     components::{Box, BoxSpot, Position},
-    resources::{Gameplay, GameplayState},
+    events::{Event, EventBus, TimedEvent},
+    resources::{Gameplay, GameplayState, Rules, SpatialIndex},
 };
 
-pub struct GameplayStateSystem;
+#[derive(Default)]
+pub struct GameplayStateSystem {
+    reader: Option<ReaderId<TimedEvent>>,
+    // spots to fill to win, counted when the level is loaded as holes may
+    // swallow boxes afterwards
+    required: usize,
+}
 
 impl<'a> System<'a> for GameplayStateSystem {
     type SystemData = (
         Write<'a, Gameplay>,
+        Write<'a, EventBus>,
+        Read<'a, Rules>,
+        Read<'a, SpatialIndex>,
         ReadStorage<'a, Position>,
         ReadStorage<'a, Box>,
         ReadStorage<'a, BoxSpot>,
     );
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.reader = Some(world.fetch_mut::<EventBus>().register_reader());
+    }
+
     fn run(&mut self, data: Self::SystemData) {
-        let (mut gameplay_state, positions, boxes, box_spots) = data;
+        let (mut gameplay_state, mut event_bus, rules, spatial_index, positions, boxes, box_spots) =
+            data;
+        let reader = self
+            .reader
+            .as_mut()
+            .expect("expected setup to register a reader");
+
+        // Only recompute when the board changed
+        let mut board_changed = false;
+        let mut level_loaded = false;
+        for timed_event in event_bus.read(reader) {
+            board_changed |= timed_event.event.changes_board();
+            level_loaded |= matches!(timed_event.event, Event::LevelLoaded);
+        }
+        if !board_changed {
+            return;
+        }
 
-        let boxes_by_position: HashMap<(u8, u8), &Box> = (&positions, &boxes)
+        // Every box must be on a spot, or every spot filled when boxes outnumber spots
+        if level_loaded {
+            let spots_count = (&box_spots, &positions).join().count();
+            self.required = spots_count.min((&boxes).join().count());
+        }
+        let placed_count = (&box_spots, &positions)
             .join()
-            .map(|t| ((t.0.x, t.0.y), t.1))
-            .collect::<HashMap<_, _>>();
-
-        for (box_spot, position) in (&box_spots, &positions).join() {
-            if let Some(the_box) = boxes_by_position.get(&(position.x, position.y)) {
-                if the_box.color == box_spot.color {
-                    // continue
-                } else {
-                    return;
-                }
-            } else {
-                gameplay_state.state = GameplayState::Playing;
-                return;
-            }
+            .filter(|(box_spot, position)| {
+                spatial_index
+                    .at(position.x, position.y)
+                    .iter()
+                    .filter_map(|e| boxes.get(*e))
+                    .any(|the_box| rules.fits(&the_box.color, &box_spot.color))
+            })
+            .count();
+
+        let state = if placed_count == self.required {
+            GameplayState::Won
+        } else {
+            GameplayState::Playing
+        };
+
+        if state == GameplayState::Won && gameplay_state.state != GameplayState::Won {
+            event_bus.push(Event::LevelCompleted);
         }
+        gameplay_state.state = state;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ggez::event::KeyCode;
+    use specs::WorldExt;
+
+    use crate::components::register_components;
+    use crate::map::load_map;
+    use crate::resources::{register_resources, InputQueue};
+    use crate::systems::dispatcher;
+
+    use super::*;
+
+    // Runs the level headless, one frame per key, giving the state after each key
+    fn play(map: &str, keys: &[KeyCode]) -> Vec<GameplayState> {
+        let mut world = World::new();
+        register_components(&mut world);
+        register_resources(&mut world);
+        let mut dispatcher = dispatcher();
+        dispatcher.setup(&mut world);
+        load_map(&mut world, map.to_string()).expect("expected a valid level");
+
+        let mut frame = |world: &mut World| {
+            dispatcher.dispatch(world);
+            world.maintain();
+            world.read_resource::<Gameplay>().state
+        };
+        let mut states = vec![frame(&mut world)];
+        for key in keys {
+            world.write_resource::<InputQueue>().keys_pressed.push(*key);
+            states.push(frame(&mut world));
+        }
+        states
+    }
+
+    #[test]
+    fn box_starting_on_a_spot_counts() {
+        let map = "
+        W W W W W W
+        W P B . S W
+        W . * . . W
+        W W W W W W
+        ";
+        let states = play(map, &[KeyCode::Right, KeyCode::Right]);
+        assert_eq!(
+            states,
+            vec![
+                GameplayState::Playing,
+                GameplayState::Playing,
+                GameplayState::Won
+            ]
+        );
+    }
+
+    #[test]
+    fn box_on_a_spot_of_another_color_does_not_count() {
+        let map = "
+        ; color R red 200 60 60
+        ; color B blue 60 90 200
+        W W W W W W
+        W P RB . BS W
+        W . BB . RS W
+        W W W W W W
+        ";
+        let states = play(map, &[KeyCode::Right, KeyCode::Right]);
+        assert_eq!(states.last(), Some(&GameplayState::Playing));
+    }
 
-        gameplay_state.state = GameplayState::Won;
+    #[test]
+    fn box_lost_in_a_hole_does_not_win() {
+        // one of the two boxes needed is pushed into the hole, the other one
+        // filling a spot
+        let map = "
+        W W W W W W
+        W P B H . W
+        W . B . S W
+        W . . . S W
+        W W W W W W
+        ";
+        let keys = [
+            KeyCode::Right,
+            KeyCode::Left,
+            KeyCode::Down,
+            KeyCode::Right,
+            KeyCode::Right,
+        ];
+        let states = play(map, &keys);
+        assert!(states.iter().all(|s| *s == GameplayState::Playing));
     }
 }
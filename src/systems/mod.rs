@@ -1,9 +1,65 @@
+use specs::{Dispatcher, DispatcherBuilder};
+
+mod audio_system;
+mod debug_system;
+mod door_system;
 mod event_system;
 mod gameplay_state_system;
 mod input_system;
+mod movement_system;
 mod rendering_system;
+mod spatial_index_system;
+mod timed;
 
+pub use self::audio_system::AudioSystem;
+pub use self::debug_system::DebugSystem;
+pub use self::door_system::DoorSystem;
 pub use self::event_system::EventSystem;
 pub use self::gameplay_state_system::GameplayStateSystem;
 pub use self::input_system::InputSystem;
+pub use self::movement_system::MovementSystem;
 pub use self::rendering_system::RenderingSystem;
+pub use self::spatial_index_system::SpatialIndexSystem;
+pub use self::timed::Timed;
+
+// Systems run every frame, in dependency order and in parallel where they can.
+// Call `setup` on the dispatcher before publishing events so subscribers see them all.
+pub fn dispatcher() -> Dispatcher<'static, 'static> {
+    // The spatial index is refreshed before movement, catching levels loaded between
+    // frames, the movement system then updating it with the moves it makes
+    DispatcherBuilder::new()
+        .with(
+            Timed::new("spatial_index", SpatialIndexSystem::default()),
+            "spatial_index",
+            &[],
+        )
+        .with(Timed::new("input", InputSystem {}), "input", &[])
+        .with(
+            Timed::new("movement", MovementSystem {}),
+            "movement",
+            &["input", "spatial_index"],
+        )
+        .with(
+            Timed::new("doors", DoorSystem::default()),
+            "doors",
+            &["movement"],
+        )
+        .with(
+            Timed::new("events", EventSystem::default()),
+            "events",
+            &["doors"],
+        )
+        .with(
+            Timed::new("gameplay_state", GameplayStateSystem::default()),
+            "gameplay_state",
+            &["events"],
+        )
+        .with(
+            Timed::new("audio", AudioSystem::default()),
+            "audio",
+            &["gameplay_state"],
+        )
+        // not timed, as it writes the debug info holding the timings
+        .with(DebugSystem::default(), "debug", &["gameplay_state"])
+        .build()
+}
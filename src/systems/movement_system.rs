@@ -0,0 +1,330 @@
+use specs::{join::Join, Entities, Entity, Read, ReadStorage, System, Write, WriteStorage};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use crate::components::{
+    Direction, Hole, Ice, Immovable, Movable, MoveIntent, OneWay, Player, Position, Teleporter,
+};
+use crate::events::{EntityMoved, Event, EventBus};
+use crate::resources::{Gameplay, MoveHistory, MoveRule, Rules, SpatialIndex};
+
+type Cell = (i32, i32);
+
+// What stands on the tiles, as far as the movement rules are concerned
+struct Terrain<'s, 'a> {
+    movables: &'s ReadStorage<'a, Movable>,
+    immovables: &'s ReadStorage<'a, Immovable>,
+    ice: &'s ReadStorage<'a, Ice>,
+    one_ways: &'s ReadStorage<'a, OneWay>,
+    holes: &'s ReadStorage<'a, Hole>,
+}
+
+impl Terrain<'_, '_> {
+    fn on<'i>(&self, spatial_index: &'i SpatialIndex, (x, y): Cell) -> Option<&'i [Entity]> {
+        match (u8::try_from(x), u8::try_from(y)) {
+            (Ok(x), Ok(y)) => Some(spatial_index.at(x, y)),
+            _ => None,
+        }
+    }
+
+    fn movable(&self, spatial_index: &SpatialIndex, cell: Cell) -> Option<Entity> {
+        let on_tile = self.on(spatial_index, cell)?;
+        on_tile
+            .iter()
+            .find(|e| self.movables.contains(**e))
+            .copied()
+    }
+
+    fn hole(&self, spatial_index: &SpatialIndex, cell: Cell) -> Option<Entity> {
+        let on_tile = self.on(spatial_index, cell)?;
+        on_tile.iter().find(|e| self.holes.contains(**e)).copied()
+    }
+
+    fn is_ice(&self, spatial_index: &SpatialIndex, cell: Cell) -> bool {
+        let on_tile = self.on(spatial_index, cell).unwrap_or_default();
+        on_tile.iter().any(|e| self.ice.contains(*e))
+    }
+
+    // Event telling why a tile can't be entered in a direction, leaving aside the
+    // movables standing on it
+    fn blocked(
+        &self,
+        spatial_index: &SpatialIndex,
+        cell: Cell,
+        direction: Direction,
+        is_player: bool,
+    ) -> Option<Event> {
+        let on_tile = match self.on(spatial_index, cell) {
+            Some(on_tile) => on_tile,
+            None => return Some(Event::PlayerHitObstacle),
+        };
+        let wrong_way = |e: &Entity| {
+            self.one_ways
+                .get(*e)
+                .is_some_and(|one_way| one_way.direction != direction)
+        };
+        if on_tile.iter().any(|e| self.immovables.contains(*e)) {
+            Some(Event::PlayerHitObstacle)
+        } else if on_tile.iter().any(wrong_way) {
+            Some(Event::OneWayBlocked)
+        } else if is_player && on_tile.iter().any(|e| self.holes.contains(*e)) {
+            Some(Event::PlayerHitObstacle)
+        } else {
+            None
+        }
+    }
+}
+
+fn place(
+    positions: &mut WriteStorage<Position>,
+    spatial_index: &mut SpatialIndex,
+    entity: Entity,
+    (x, y): Cell,
+) {
+    if let Some(position) = positions.get_mut(entity) {
+        position.x = x as u8;
+        position.y = y as u8;
+        spatial_index.insert(entity, position.x, position.y);
+    }
+}
+
+// Carries out the move intents: walking, pushing or pulling boxes, or undoing
+pub struct MovementSystem;
+
+impl<'a> System<'a> for MovementSystem {
+    type SystemData = (
+        Write<'a, EventBus>,
+        Write<'a, Gameplay>,
+        Write<'a, MoveHistory>,
+        Write<'a, SpatialIndex>,
+        Read<'a, Rules>,
+        Entities<'a>,
+        WriteStorage<'a, MoveIntent>,
+        WriteStorage<'a, Position>,
+        ReadStorage<'a, Movable>,
+        ReadStorage<'a, Immovable>,
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, Ice>,
+        ReadStorage<'a, OneWay>,
+        ReadStorage<'a, Teleporter>,
+        ReadStorage<'a, Hole>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            mut event_bus,
+            mut gameplay,
+            mut move_history,
+            mut spatial_index,
+            rules,
+            entities,
+            mut intents,
+            mut positions,
+            movables,
+            immovables,
+            players,
+            ice,
+            one_ways,
+            teleporters,
+            holes,
+        ) = data;
+        let terrain = Terrain {
+            movables: &movables,
+            immovables: &immovables,
+            ice: &ice,
+            one_ways: &one_ways,
+            holes: &holes,
+        };
+
+        // Cell each teleporter sends to, the other one of its pair
+        let teleporter_cells: Vec<(u8, Cell)> = (&teleporters, &positions)
+            .join()
+            .map(|(teleporter, p)| (teleporter.pair, (p.x as i32, p.y as i32)))
+            .collect();
+        let exits: HashMap<Cell, Cell> = teleporter_cells
+            .iter()
+            .flat_map(|(pair, cell)| {
+                teleporter_cells
+                    .iter()
+                    .find(|(other_pair, other)| other_pair == pair && other != cell)
+                    .map(|(_, exit)| (*cell, *exit))
+            })
+            .collect();
+
+        let requested: Vec<(Entity, MoveIntent)> = (&entities, &intents)
+            .join()
+            .map(|(entity, intent)| (entity, *intent))
+            .collect();
+        intents.clear();
+
+        // The spatial index is kept up to date between moves, so that movers see
+        // where the previous ones went
+        for (mover, intent) in requested {
+            let direction = match intent {
+                MoveIntent::Move(direction) => direction,
+                // Undo restores the positions saved before the last move
+                MoveIntent::Undo => {
+                    if let Some(previous_positions) = move_history.moves.pop() {
+                        if previous_positions.len() > 1 {
+                            gameplay.pushes_count = gameplay.pushes_count.saturating_sub(1);
+                        }
+                        for (id, previous_position) in previous_positions {
+                            let entity = entities.entity(id);
+                            if let Some(position) = positions.get_mut(entity) {
+                                *position = previous_position;
+                                spatial_index.insert(entity, position.x, position.y);
+                            }
+                        }
+                        gameplay.moves_count = gameplay.moves_count.saturating_sub(1);
+                        event_bus.push(Event::MoveUndone);
+                    }
+                    continue;
+                }
+            };
+
+            let position = match positions.get(mover) {
+                Some(position) => *position,
+                None => continue,
+            };
+            let (dx, dy) = direction.delta();
+            let (x, y) = (position.x as i32, position.y as i32);
+
+            let mut to_move = Vec::new();
+            match rules.move_rule {
+                // Walk from the mover in the direction of the move and collect everything
+                // that has to move along, until a gap or an obstacle
+                MoveRule::Push => {
+                    let (mut x, mut y) = (x, y);
+                    let mut blocked = None;
+                    while let Some(entity) = terrain.movable(&spatial_index, (x, y)) {
+                        // players can't push each other
+                        if !to_move.is_empty() && players.contains(entity) {
+                            blocked = Some(Event::PlayerHitObstacle);
+                        }
+                        blocked = blocked.or_else(|| {
+                            let is_player = players.contains(entity);
+                            terrain.blocked(&spatial_index, (x + dx, y + dy), direction, is_player)
+                        });
+                        to_move.push(entity);
+                        x += dx;
+                        y += dy;
+                    }
+                    // the mover is first in line, followed by the boxes it pushes
+                    let pushed = to_move.len().saturating_sub(1);
+                    if let Some(event) = blocked {
+                        to_move.clear();
+                        event_bus.push(event);
+                    } else if rules.max_push_chain.is_some_and(|max| pushed > max) {
+                        to_move.clear();
+                        event_bus.push(Event::PushChainTooLong);
+                    }
+                }
+                // The mover needs a free tile ahead and drags along the box behind it,
+                // when that box may enter the tile the mover leaves
+                MoveRule::Pull => {
+                    let ahead = (x + dx, y + dy);
+                    let blocked = match terrain.movable(&spatial_index, ahead) {
+                        Some(_) => Some(Event::PlayerHitObstacle),
+                        None => terrain.blocked(&spatial_index, ahead, direction, true),
+                    };
+                    if let Some(event) = blocked {
+                        event_bus.push(event);
+                    } else if let Some(mover) = terrain.movable(&spatial_index, (x, y)) {
+                        to_move.push(mover);
+                        let pulled = terrain
+                            .movable(&spatial_index, (x - dx, y - dy))
+                            .filter(|e| !players.contains(*e))
+                            .filter(|_| {
+                                terrain
+                                    .blocked(&spatial_index, (x, y), direction, false)
+                                    .is_none()
+                            });
+                        to_move.extend(pulled);
+                    }
+                }
+            }
+
+            // the player moves along with the boxes it pushes or pulls
+            match to_move.len() {
+                0 => continue,
+                1 => {
+                    gameplay.moves_count += 1;
+                    event_bus.push(Event::PlayerStepped);
+                }
+                _ => {
+                    gameplay.moves_count += 1;
+                    gameplay.pushes_count += 1;
+                    event_bus.push(match rules.move_rule {
+                        MoveRule::Push => Event::BoxPushed,
+                        MoveRule::Pull => Event::BoxPulled,
+                    });
+                }
+            }
+
+            let mut previous_positions = Vec::new();
+            for entity in to_move.iter() {
+                if let Some(position) = positions.get(*entity) {
+                    previous_positions.push((entity.id(), *position));
+                    let cell = (position.x as i32 + dx, position.y as i32 + dy);
+                    place(&mut positions, &mut spatial_index, *entity, cell);
+                }
+            }
+
+            // Then the tiles act on what moved, the foremost first so the others can
+            // follow: holes swallow boxes, teleporters send to their pair and ice
+            // keeps things sliding
+            if rules.move_rule == MoveRule::Push {
+                to_move.reverse();
+            }
+            let mut filled_hole = false;
+            for entity in to_move.iter().copied() {
+                let is_player = players.contains(entity);
+                let mut slid = false;
+                while let Some(position) = positions.get(entity) {
+                    let cell = (position.x as i32, position.y as i32);
+                    let hole = terrain.hole(&spatial_index, cell);
+                    if let Some(hole) = hole.filter(|_| !is_player) {
+                        spatial_index.remove(hole.id());
+                        spatial_index.remove(entity.id());
+                        let _ = entities.delete(hole);
+                        let _ = entities.delete(entity);
+                        filled_hole = true;
+                        event_bus.push(Event::HoleFilled);
+                        break;
+                    }
+                    if let Some(exit) = exits.get(&cell) {
+                        if terrain.movable(&spatial_index, *exit).is_none() {
+                            place(&mut positions, &mut spatial_index, entity, *exit);
+                            event_bus.push(Event::Teleported);
+                        }
+                        break;
+                    }
+                    let next = (cell.0 + dx, cell.1 + dy);
+                    if !terrain.is_ice(&spatial_index, cell)
+                        || terrain.movable(&spatial_index, next).is_some()
+                        || terrain
+                            .blocked(&spatial_index, next, direction, is_player)
+                            .is_some()
+                    {
+                        break;
+                    }
+                    place(&mut positions, &mut spatial_index, entity, next);
+                    slid = true;
+                }
+                if slid {
+                    event_bus.push(Event::IceSlid);
+                }
+            }
+
+            for entity in to_move {
+                event_bus.push(Event::EntityMoved(EntityMoved { id: entity.id() }));
+            }
+            // a filled hole can't be dug again, so the moves before it can't be undone
+            if filled_hole {
+                move_history.moves.clear();
+            } else {
+                move_history.moves.push(previous_positions);
+            }
+        }
+    }
+}
@@ -3,13 +3,17 @@ use ggez::mint as mi;
 use ggez::{timer, Context};
 use graphics::spritebatch::SpriteBatch;
 use itertools::Itertools;
-use specs::{join::Join, Read, ReadStorage, System};
+use specs::{join::Join, Entities, Read, ReadStorage, System};
 use std::collections::HashMap;
 use std::time::Duration;
 
-use crate::components::{Position, Renderable, RenderableKind};
-use crate::constants::TILE_WIDTH;
-use crate::resources::{Gameplay, Time};
+use crate::components::{BoxColor, Player, Position, Renderable, Sprite};
+use crate::constants::{PLAYER_COLORS, TILE_WIDTH};
+use crate::daily::{puzzle_number, Daily};
+use crate::editor::{Editor, PANEL_STEP, PANEL_X, PANEL_Y};
+use crate::map::CurrentLevel;
+use crate::resources::{DebugInfo, Gameplay, Palette, Time};
+use crate::theme::Themes;
 
 pub struct RenderingSystem<'a> {
     pub context: &'a mut Context,
@@ -32,12 +36,99 @@ impl RenderingSystem<'_> {
         .expect("expect drawing queued text");
     }
 
-    pub fn get_image(&mut self, renderable: &Renderable, delta: Duration) -> String {
-        let path_index = match renderable.kind() {
-            RenderableKind::Static => 0,
-            RenderableKind::Animated => ((delta.as_millis() % 1000) / 250) as usize,
+    // Colored sprites without a dedicated image are drawn as tinted neutral ones,
+    // and players past the first as the first one tinted with their color
+    pub fn get_image(
+        &self,
+        themes: &Themes,
+        palette: &Palette,
+        sprite: &Sprite,
+        delta: Duration,
+    ) -> Option<(String, Color)> {
+        if let Some(image_path) = themes.image(sprite, delta) {
+            return Some((image_path, Color::WHITE));
+        }
+
+        let (neutral, (r, g, b)) = match sprite {
+            Sprite::Box(color) => (Sprite::Box(BoxColor::Neutral), palette.rgb(color)?),
+            Sprite::BoxSpot(color) => (Sprite::BoxSpot(BoxColor::Neutral), palette.rgb(color)?),
+            Sprite::Player(index) => (Sprite::Player(0), *PLAYER_COLORS.get(*index as usize)?),
+            _ => return None,
         };
-        renderable.path(path_index)
+        let image_path = themes.image(&neutral, delta)?;
+        Some((image_path, Color::from_rgb(r, g, b)))
+    }
+
+    // Missing files are dropped when the themes load, this leaves out the files
+    // that can't be decoded
+    fn load_image(&mut self, image_path: &str) -> Option<Image> {
+        Image::new(self.context, image_path).ok()
+    }
+
+    pub fn draw_debug(&mut self, debug_info: &DebugInfo, lines: Vec<String>) {
+        let timings = debug_info
+            .timings
+            .lock()
+            .map(|timings| timings.clone())
+            .unwrap_or_default();
+        let lines: Vec<String> = lines
+            .into_iter()
+            .chain(timings.iter().map(|(name, duration)| {
+                format!("{}: {:.2} ms", name, duration.as_secs_f64() * 1000.0)
+            }))
+            .chain(debug_info.events.iter().cloned())
+            .collect();
+
+        let panel = graphics::Rect::new(680.0, 220.0, 310.0, 20.0 * lines.len() as f32 + 10.0);
+        let background = graphics::Mesh::new_rectangle(
+            self.context,
+            graphics::DrawMode::fill(),
+            panel,
+            Color::new(0.9, 0.9, 0.9, 0.9),
+        )
+        .expect("expected debug panel");
+        graphics::draw(self.context, &background, DrawParam::new()).expect("expected render");
+        for (i, line) in lines.iter().enumerate() {
+            self.draw_text(line, panel.x + 5.0, panel.y + 5.0 + i as f32 * 20.0);
+        }
+    }
+
+    pub fn draw_editor(
+        &mut self,
+        editor: &Editor,
+        themes: &Themes,
+        palette: &Palette,
+        delta: Duration,
+    ) {
+        let mode = if editor.playtesting {
+            "PLAYTEST"
+        } else {
+            "EDITOR"
+        };
+        self.draw_text(mode, 525.0, 160.0);
+        for (i, message) in editor.messages.iter().enumerate() {
+            self.draw_text(message, 525.0, 180.0 + i as f32 * 20.0);
+        }
+        if editor.playtesting {
+            return;
+        }
+
+        for (i, brush) in editor.brushes().iter().enumerate() {
+            let y = PANEL_Y + i as f32 * PANEL_STEP;
+            let image = brush
+                .sprite()
+                .and_then(|sprite| self.get_image(themes, palette, &sprite, delta));
+            let image = image.and_then(|(path, color)| Some((self.load_image(&path)?, color)));
+            if let Some((image, color)) = image {
+                let draw_params = DrawParam::new()
+                    .dest(mi::Point2 { x: PANEL_X, y })
+                    .color(color);
+                graphics::draw(self.context, &image, draw_params).expect("expected render");
+            }
+            let marker = if *brush == editor.brush { "> " } else { "" };
+            let label = format!("{}{}", marker, brush.name());
+            self.draw_text(&label, PANEL_X + TILE_WIDTH + 8.0, y + 8.0);
+        }
     }
 }
 
@@ -45,13 +136,34 @@ impl<'a> System<'a> for RenderingSystem<'a> {
     type SystemData = (
         Read<'a, Gameplay>,
         Read<'a, Time>,
+        Read<'a, Themes>,
+        Read<'a, Palette>,
+        Read<'a, Editor>,
+        Read<'a, CurrentLevel>,
+        Read<'a, Daily>,
+        Read<'a, DebugInfo>,
+        Entities<'a>,
         ReadStorage<'a, Position>,
         ReadStorage<'a, Renderable>,
+        ReadStorage<'a, Player>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
         graphics::clear(self.context, Color::WHITE);
-        let (gameplay, time, position, renderables) = data;
+        let (
+            gameplay,
+            time,
+            themes,
+            palette,
+            editor,
+            current_level,
+            daily,
+            debug_info,
+            entities,
+            position,
+            renderables,
+            players,
+        ) = data;
 
         // Should change that to FlaggedStorage to maintained a sorted Entity list
         // https://specs.amethyst.rs/docs/tutorials/12_tracked.html
@@ -59,13 +171,17 @@ impl<'a> System<'a> for RenderingSystem<'a> {
         let mut rendering_batches: HashMap<u8, HashMap<String, Vec<DrawParam>>> = HashMap::new();
 
         for (position, renderable) in rendering_data.iter() {
-            let image_path = self.get_image(renderable, time.delta);
+            let (image_path, color) =
+                match self.get_image(&themes, &palette, &renderable.sprite, time.delta) {
+                    Some(image) => image,
+                    None => continue,
+                };
 
             let x = position.x as f32 * TILE_WIDTH;
             let y = position.y as f32 * TILE_WIDTH;
             let z = position.z;
 
-            let draw_params = DrawParam::new().dest(mi::Point2 { x, y });
+            let draw_params = DrawParam::new().dest(mi::Point2 { x, y }).color(color);
 
             rendering_batches
                 .entry(z)
@@ -82,7 +198,10 @@ impl<'a> System<'a> for RenderingSystem<'a> {
             .sorted_by(|a, b| Ord::cmp(&a.0, &b.0))
         {
             for (image_path, draw_params) in group {
-                let image = Image::new(self.context, image_path).expect("expected image");
+                let image = match self.load_image(image_path) {
+                    Some(image) => image,
+                    None => continue,
+                };
                 let mut sprite_batch = SpriteBatch::new(image);
 
                 for draw_param in draw_params.iter() {
@@ -99,6 +218,39 @@ impl<'a> System<'a> for RenderingSystem<'a> {
         self.draw_text(&gameplay.moves_count.to_string(), 525.0, 100.0);
         let fps = format!("FPS: {:.0}", timer::fps(self.context));
         self.draw_text(&fps, 525.0, 120.0);
+        if daily.active {
+            let title = format!("Daily #{}", puzzle_number(daily.day));
+            self.draw_text(&title, 525.0, 140.0);
+        }
+        if let Some(result) = &daily.result {
+            for (i, line) in result.lines().enumerate() {
+                self.draw_text(line, 525.0, 400.0 + i as f32 * 20.0);
+            }
+        }
+        for (i, error) in current_level.errors.iter().enumerate() {
+            self.draw_text(error, 20.0, 20.0 + i as f32 * 20.0);
+        }
+        if editor.active {
+            self.draw_editor(&editor, &themes, &palette, time.delta);
+        }
+        if debug_info.visible {
+            let entity_count = (&entities).join().count();
+            let player = match (&position, &players).join().next() {
+                Some((p, _)) => format!("player: {}, {}", p.x, p.y),
+                None => "player: none".to_string(),
+            };
+            let bounds = match (
+                position.join().map(|p| p.x).minmax().into_option(),
+                position.join().map(|p| p.y).minmax().into_option(),
+            ) {
+                (Some((min_x, max_x)), Some((min_y, max_y))) => {
+                    format!("bounds: {}..={}, {}..={}", min_x, max_x, min_y, max_y)
+                }
+                _ => "bounds: none".to_string(),
+            };
+            let lines = vec![format!("entities: {}", entity_count), player, bounds];
+            self.draw_debug(&debug_info, lines);
+        }
         graphics::present(self.context).expect("expected to present");
     }
 }
@@ -0,0 +1,45 @@
+use specs::shrev::ReaderId;
+use specs::storage::ComponentEvent;
+use specs::{Entities, ReadStorage, System, SystemData, World, WorldExt, Write};
+
+use crate::{components::Position, resources::SpatialIndex};
+
+// Applies the position changes since its last run to the spatial index
+#[derive(Default)]
+pub struct SpatialIndexSystem {
+    reader: Option<ReaderId<ComponentEvent>>,
+}
+
+impl<'a> System<'a> for SpatialIndexSystem {
+    type SystemData = (
+        Write<'a, SpatialIndex>,
+        Entities<'a>,
+        ReadStorage<'a, Position>,
+    );
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.reader = Some(world.write_storage::<Position>().register_reader());
+    }
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut spatial_index, entities, positions) = data;
+        let reader = self
+            .reader
+            .as_mut()
+            .expect("expected setup to register a reader");
+
+        for event in positions.channel().read(reader) {
+            match event {
+                ComponentEvent::Inserted(id) | ComponentEvent::Modified(id) => {
+                    let entity = entities.entity(*id);
+                    match positions.get(entity) {
+                        Some(position) => spatial_index.insert(entity, position.x, position.y),
+                        None => spatial_index.remove(*id),
+                    }
+                }
+                ComponentEvent::Removed(id) => spatial_index.remove(*id),
+            }
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use specs::{Read, System, SystemData, World};
+use std::time::Instant;
+
+use crate::resources::DebugInfo;
+
+// Wraps a system to record how long each run takes, for the debug overlay
+pub struct Timed<S> {
+    pub name: &'static str,
+    pub system: S,
+}
+
+impl<S> Timed<S> {
+    pub fn new(name: &'static str, system: S) -> Self {
+        Timed { name, system }
+    }
+}
+
+impl<'a, S> System<'a> for Timed<S>
+where
+    S: System<'a>,
+    S::SystemData: SystemData<'a>,
+{
+    type SystemData = (S::SystemData, Read<'a, DebugInfo>);
+
+    fn setup(&mut self, world: &mut World) {
+        Read::<DebugInfo>::setup(world);
+        self.system.setup(world);
+    }
+
+    fn run(&mut self, (data, debug_info): Self::SystemData) {
+        let start = Instant::now();
+        self.system.run(data);
+        debug_info.record(self.name, start.elapsed());
+    }
+}
@@ -0,0 +1,164 @@
+use ggez::{filesystem, Context};
+use log::warn;
+use serde::Deserialize;
+use specs::{World, WorldExt};
+use std::collections::HashMap;
+use std::io::Read;
+use std::time::Duration;
+
+use crate::components::{Direction, Sprite};
+
+const THEMES_DIR: &str = "/themes";
+const DEFAULT_THEME: &str = "default";
+const DEFAULT_FRAME_MS: u64 = 250;
+const BUILTIN_THEME: &str = include_str!("../resources/themes/default.toml");
+
+#[derive(Deserialize)]
+pub struct BoxSprites {
+    #[serde(rename = "box", default)]
+    pub box_frames: Vec<String>,
+    #[serde(default)]
+    pub spot: Vec<String>,
+}
+
+// A theme maps logical tile kinds and box colors to sprite files
+#[derive(Deserialize)]
+pub struct Theme {
+    pub name: String,
+    #[serde(default)]
+    pub frame_ms: Option<u64>,
+    #[serde(default)]
+    pub tiles: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    pub boxes: HashMap<String, BoxSprites>,
+}
+
+impl Theme {
+    // Built-in theme used when no default manifest can be loaded
+    pub fn builtin() -> Self {
+        toml::from_str(BUILTIN_THEME).expect("expected a valid built-in theme")
+    }
+
+    // Drops the frames whose file is missing, so that the default theme's sprite
+    // is drawn instead
+    fn drop_missing(&mut self, context: &Context) {
+        let name = &self.name;
+        let frame_lists = self.tiles.values_mut().chain(
+            self.boxes
+                .values_mut()
+                .flat_map(|sprites| vec![&mut sprites.box_frames, &mut sprites.spot]),
+        );
+        for frames in frame_lists {
+            frames.retain(|path| {
+                let exists = filesystem::exists(context, path);
+                if !exists {
+                    warn!("Theme {} is missing {}", name, path);
+                }
+                exists
+            });
+        }
+    }
+
+    fn frames(&self, sprite: &Sprite) -> Option<&Vec<String>> {
+        let frames = match sprite {
+            Sprite::Wall => self.tiles.get("wall"),
+            Sprite::Floor => self.tiles.get("floor"),
+            Sprite::Ice => self.tiles.get("ice"),
+            Sprite::OneWay(direction) => self.tiles.get(match direction {
+                Direction::Up => "one_way_up",
+                Direction::Down => "one_way_down",
+                Direction::Left => "one_way_left",
+                Direction::Right => "one_way_right",
+            }),
+            Sprite::Teleporter => self.tiles.get("teleporter"),
+            Sprite::Hole => self.tiles.get("hole"),
+            Sprite::Plate => self.tiles.get("plate"),
+            Sprite::Door { open: false } => self.tiles.get("door"),
+            Sprite::Door { open: true } => self.tiles.get("door_open"),
+            Sprite::Player(0) => self.tiles.get("player"),
+            Sprite::Player(index) => self.tiles.get(&format!("player{}", index + 1)),
+            Sprite::Box(color) => self
+                .boxes
+                .get(&color.to_string())
+                .map(|sprites| &sprites.box_frames),
+            Sprite::BoxSpot(color) => self
+                .boxes
+                .get(&color.to_string())
+                .map(|sprites| &sprites.spot),
+        };
+        frames.filter(|frames| !frames.is_empty())
+    }
+}
+
+// Resource holding every loaded theme, the first one being the default theme
+pub struct Themes {
+    themes: Vec<Theme>,
+    current: usize,
+}
+
+impl Default for Themes {
+    fn default() -> Self {
+        Self {
+            themes: vec![Theme::builtin()],
+            current: 0,
+        }
+    }
+}
+
+impl Themes {
+    pub fn current(&self) -> &Theme {
+        &self.themes[self.current]
+    }
+
+    pub fn next(&mut self) {
+        self.current = (self.current + 1) % self.themes.len();
+    }
+
+    // Image path of a sprite at a given time, falling back to the default theme
+    // when the current theme doesn't define the sprite
+    pub fn image(&self, sprite: &Sprite, time: Duration) -> Option<String> {
+        let (theme, frames) = match self.current().frames(sprite) {
+            Some(frames) => (self.current(), frames),
+            None => (&self.themes[0], self.themes[0].frames(sprite)?),
+        };
+        let frame_ms = theme.frame_ms.unwrap_or(DEFAULT_FRAME_MS).max(1) as u128;
+        let index = (time.as_millis() / frame_ms) as usize % frames.len();
+        Some(frames[index].clone())
+    }
+}
+
+fn load_theme(context: &mut Context, path: &std::path::Path) -> Result<Theme, String> {
+    let mut file = filesystem::open(context, path).map_err(|e| e.to_string())?;
+    let mut content = String::new();
+    file.read_to_string(&mut content)
+        .map_err(|e| e.to_string())?;
+    toml::from_str(&content).map_err(|e| e.to_string())
+}
+
+pub fn initialize_themes(world: &mut World, context: &mut Context) {
+    let mut themes = world.write_resource::<Themes>();
+    let paths = filesystem::read_dir(context, THEMES_DIR)
+        .map(|paths| paths.collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    let mut loaded = Vec::new();
+    for path in paths
+        .iter()
+        .filter(|p| p.extension().is_some_and(|e| e == "toml"))
+    {
+        match load_theme(context, path) {
+            Ok(theme) => loaded.push(theme),
+            Err(e) => warn!("Skipping theme {}: {}", path.display(), e),
+        }
+    }
+    loaded.sort_by(|a, b| a.name.cmp(&b.name));
+
+    // Keep the built-in theme as default unless a manifest replaces it
+    if let Some(index) = loaded.iter().position(|t| t.name == DEFAULT_THEME) {
+        themes.themes[0] = loaded.remove(index);
+    }
+    themes.themes.append(&mut loaded);
+    for theme in themes.themes.iter_mut() {
+        theme.drop_missing(context);
+    }
+}
@@ -0,0 +1,319 @@
+use ggez::event::KeyCode;
+use specs::{World, WorldExt};
+
+use crate::components::Direction;
+use crate::daily::Daily;
+use crate::editor::Editor;
+use crate::map::{reset_world, spawn_level, CurrentLevel, Level, Tile};
+
+// One of the 8 symmetries of the grid: an optional horizontal mirror followed
+// by clockwise quarter turns
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Transform {
+    pub rotation: u8,
+    pub mirror: bool,
+}
+
+impl Transform {
+    pub fn all() -> Vec<Transform> {
+        (0..8)
+            .map(|i| Transform {
+                rotation: i % 4,
+                mirror: i >= 4,
+            })
+            .collect()
+    }
+
+    // "90", "180", "270", "mirror" or a comma separated combination like "mirror,90"
+    pub fn parse(text: &str) -> Result<Transform, String> {
+        let mut transform = Transform::default();
+        for part in text.split(',').map(str::trim) {
+            match part {
+                "0" => transform.rotation = 0,
+                "90" => transform.rotation = 1,
+                "180" => transform.rotation = 2,
+                "270" => transform.rotation = 3,
+                "mirror" => transform.mirror = true,
+                _ => return Err(format!("unknown transform '{}'", part)),
+            }
+        }
+        Ok(transform)
+    }
+
+    // This transform followed by another one
+    pub fn then(&self, other: Transform) -> Transform {
+        // Mirroring after a rotation is the mirror followed by the opposite rotation
+        let rotation = if other.mirror {
+            (4 - self.rotation % 4) % 4
+        } else {
+            self.rotation % 4
+        };
+        Transform {
+            rotation: (rotation + other.rotation) % 4,
+            mirror: self.mirror != other.mirror,
+        }
+    }
+
+    fn dimensions(&self, width: usize, height: usize) -> (usize, usize) {
+        if self.rotation.is_multiple_of(2) {
+            (width, height)
+        } else {
+            (height, width)
+        }
+    }
+
+    pub fn point(&self, x: usize, y: usize, width: usize, height: usize) -> (usize, usize) {
+        let (mut x, mut y, mut width, mut height) = (x, y, width, height);
+        if self.mirror {
+            x = width - 1 - x;
+        }
+        for _ in 0..self.rotation % 4 {
+            let rotated = (height - 1 - y, x);
+            x = rotated.0;
+            y = rotated.1;
+            std::mem::swap(&mut width, &mut height);
+        }
+        (x, y)
+    }
+
+    pub fn direction(&self, dx: i32, dy: i32) -> (i32, i32) {
+        let (mut dx, mut dy) = (dx, dy);
+        if self.mirror {
+            dx = -dx;
+        }
+        for _ in 0..self.rotation % 4 {
+            let rotated = (-dy, dx);
+            dx = rotated.0;
+            dy = rotated.1;
+        }
+        (dx, dy)
+    }
+
+    // LURD moves of the transformed level, pushes keeping their uppercase letter
+    pub fn moves(&self, moves: &str) -> String {
+        moves
+            .chars()
+            .map(|c| {
+                let (dx, dy) = match c.to_ascii_lowercase() {
+                    'l' => (-1, 0),
+                    'u' => (0, -1),
+                    'r' => (1, 0),
+                    'd' => (0, 1),
+                    _ => return c,
+                };
+                let letter = match self.direction(dx, dy) {
+                    (-1, 0) => 'l',
+                    (0, -1) => 'u',
+                    (1, 0) => 'r',
+                    _ => 'd',
+                };
+                if c.is_ascii_uppercase() {
+                    letter.to_ascii_uppercase()
+                } else {
+                    letter
+                }
+            })
+            .collect()
+    }
+
+    pub fn apply(&self, level: &Level) -> Level {
+        let (width, height) = self.dimensions(level.width, level.height);
+        let mut transformed = Level::new(width, height, level.palette.clone());
+        transformed.rule_mode = level.rule_mode;
+        for y in 0..level.height {
+            for x in 0..level.width {
+                let (tx, ty) = self.point(x, y, level.width, level.height);
+                let mut cell = level.cell(x, y).clone();
+                if let Tile::OneWay(direction) = cell.tile {
+                    let (dx, dy) = direction.delta();
+                    let (dx, dy) = self.direction(dx, dy);
+                    cell.tile = Tile::OneWay(Direction::from_delta(dx, dy).unwrap_or(direction));
+                }
+                *transformed.cell_mut(tx, ty) = cell;
+            }
+        }
+        transformed
+    }
+}
+
+// Transform applied to every loaded level
+#[derive(Default)]
+pub struct Orientation {
+    pub transform: Transform,
+}
+
+pub fn orient(world: &World, level: Level) -> Level {
+    world.read_resource::<Orientation>().transform.apply(&level)
+}
+
+// R rotates the current level clockwise and M mirrors it, restarting it
+pub fn handle_key(world: &mut World, keycode: KeyCode) -> bool {
+    let step = match keycode {
+        KeyCode::R => Transform {
+            rotation: 1,
+            mirror: false,
+        },
+        KeyCode::M => Transform {
+            rotation: 0,
+            mirror: true,
+        },
+        _ => return false,
+    };
+    if world.read_resource::<Editor>().active {
+        return false;
+    }
+
+    let (previous, level) = {
+        let current = world.read_resource::<CurrentLevel>();
+        if !current.errors.is_empty() {
+            return true;
+        }
+        (current.level.to_string(), step.apply(&current.level))
+    };
+    {
+        let mut orientation = world.write_resource::<Orientation>();
+        orientation.transform = orientation.transform.then(step);
+    }
+    reset_world(world);
+    spawn_level(world, level);
+
+    // Still the same daily puzzle, only seen from another side
+    let current = world.read_resource::<CurrentLevel>().level.to_string();
+    let mut daily = world.write_resource::<Daily>();
+    if daily.level == previous {
+        daily.level = current;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::{solve, Board, DIRECTIONS, MAX_NODES};
+
+    const LEVEL: &str = "
+    W W W W W W W
+    W P . . . . W
+    W . B . B S W
+    W . . W . . W
+    W . . . . S W
+    W W W W W W W
+    ";
+
+    fn level() -> Level {
+        Level::parse(LEVEL).expect("expected a valid level")
+    }
+
+    // Play LURD moves on a level, failing on a move into a wall or a push mismatch
+    fn solves(level: &Level, moves: &str) -> bool {
+        let (board, state) = Board::new(level);
+        let mut state = state.expect("expected a player");
+        for c in moves.chars() {
+            let direction = DIRECTIONS
+                .iter()
+                .find(|d| d.2 == c.to_ascii_lowercase())
+                .copied()
+                .expect("expected a LURD move");
+            let next = board
+                .step(state.player, direction)
+                .expect("expected a cell");
+            if state.boxes.iter().any(|(cell, _)| *cell == next) {
+                assert!(c.is_ascii_uppercase(), "push {} written as a walk", c);
+                state = board
+                    .push(&state, state.player, direction)
+                    .expect("expected a push");
+            } else {
+                assert!(!board.walls[next], "walked into a wall");
+                state.player = next;
+            }
+        }
+        board.is_solved(&state)
+    }
+
+    #[test]
+    fn then_composes_transforms() {
+        let mirror = Transform {
+            rotation: 0,
+            mirror: true,
+        };
+        let rotate = Transform {
+            rotation: 1,
+            mirror: false,
+        };
+        assert_eq!(
+            mirror.then(rotate),
+            Transform {
+                rotation: 1,
+                mirror: true,
+            }
+        );
+        assert_eq!(
+            rotate.then(mirror),
+            Transform {
+                rotation: 3,
+                mirror: true,
+            }
+        );
+
+        let (width, height) = (4, 3);
+        for first in Transform::all() {
+            for second in Transform::all() {
+                let composed = first.then(second);
+                let (w, h) = first.dimensions(width, height);
+                for (x, y) in (0..height).flat_map(|y| (0..width).map(move |x| (x, y))) {
+                    let (fx, fy) = first.point(x, y, width, height);
+                    assert_eq!(
+                        composed.point(x, y, width, height),
+                        second.point(fx, fy, w, h)
+                    );
+                }
+                for (dx, dy) in [(1, 0), (0, 1)].iter() {
+                    let (fx, fy) = first.direction(*dx, *dy);
+                    assert_eq!(composed.direction(*dx, *dy), second.direction(fx, fy));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn transforms_are_distinct() {
+        let mut levels: Vec<String> = Transform::all()
+            .iter()
+            .map(|transform| transform.apply(&level()).to_string())
+            .collect();
+        levels.sort();
+        levels.dedup();
+        assert_eq!(levels.len(), 8);
+    }
+
+    #[test]
+    fn directions_follow_points() {
+        let (width, height) = (4, 3);
+        for transform in Transform::all() {
+            let (x0, y0) = transform.point(1, 1, width, height);
+            for (dx, dy) in [(-1, 0), (0, -1), (1, 0), (0, 1)].iter() {
+                let (x1, y1) = transform.point((1 + dx) as usize, (1 + dy) as usize, width, height);
+                let moved = (x1 as i32 - x0 as i32, y1 as i32 - y0 as i32);
+                assert_eq!(transform.direction(*dx, *dy), moved);
+            }
+        }
+    }
+
+    #[test]
+    fn moves_solve_the_transformed_level() {
+        let level = level();
+        let solution = solve(&level, MAX_NODES)
+            .solution
+            .expect("expected a solution");
+        assert!(solves(&level, &solution.moves));
+        for transform in Transform::all() {
+            let moves = transform.moves(&solution.moves);
+            assert!(
+                solves(&transform.apply(&level), &moves),
+                "{:?} doesn't solve with {}",
+                transform,
+                moves
+            );
+        }
+    }
+}